@@ -0,0 +1,96 @@
+/* *
+ * tsc - Implement Threefish, Skein, and CATENA cryptographic algorithms.
+ * Copyright (C) 2025 Stuart Calder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Unpadded standard-alphabet Base64, as used by PHC-style hash strings.
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn decode_char(c: u8) -> Option<u32> {
+    match c {
+        b'A'..=b'Z' => Some((c - b'A') as u32),
+        b'a'..=b'z' => Some((c - b'a') as u32 + 26),
+        b'0'..=b'9' => Some((c - b'0') as u32 + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None
+    }
+}
+
+/// Encode @input as unpadded Base64.
+pub fn encode(input: &[u8]) -> String {
+    let mut out = String::with_capacity((input.len() * 4).div_ceil(3));
+    for chunk in input.chunks(3) {
+        let mut n = 0u32;
+        for (i, &b) in chunk.iter().enumerate() {
+            n |= (b as u32) << (16 - (8 * i));
+        }
+        // 2, 3 or 4 characters for 1, 2 or 3 input bytes.
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[((n >> (18 - (6 * i))) & 0x3F) as usize] as char);
+        }
+    }
+    out
+}
+
+/// Decode unpadded Base64. Returns None on any invalid character, an impossible length,
+/// or nonzero trailing bits.
+pub fn decode(input: &str) -> Option<Vec<u8>> {
+    let input = input.as_bytes();
+    if input.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    for chunk in input.chunks(4) {
+        let mut n = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            n |= decode_char(c)? << (18 - (6 * i));
+        }
+        let num_bytes = chunk.len() - 1;
+        // Reject encodings whose unused trailing bits are set; they are not canonical.
+        if n & ((1u32 << (24 - (8 * num_bytes))) - 1) != 0 {
+            return None;
+        }
+        for i in 0..num_bytes {
+            out.push((n >> (16 - (8 * i))) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let data: Vec<u8> = (0u8..=255).collect();
+        for len in 0..data.len() {
+            let enc = encode(&data[..len]);
+            assert!(!enc.contains('='));
+            assert_eq!(decode(&enc).unwrap(), &data[..len]);
+        }
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode(b"fooba"), "Zm9vYmE");
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        assert!(decode("Zm9vY").is_none());
+        assert!(decode("Zm9=").is_none());
+        assert!(decode("Zm9vYmF").is_none()); // Trailing bits set.
+    }
+}
@@ -19,6 +19,15 @@
 use crate::tf512;
 use crate::ubi512;
 use crate::skein512;
+use crate::error::Error;
+use crate::csprng::Csprng;
+use crate::secure_buffer::{SecureBuffer, TAG_MAP};
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::base64;
+use crate::phc;
+#[cfg(feature = "async")]
+use crate::task::{self, TaskFuture};
 
 use tf512::{
     NUM_BLOCK_BYTES,
@@ -68,27 +77,64 @@ pub const WITH_PHI_VERSION_ID: [u8; NUM_KEY_BYTES] = [
     0xF3u8, 0x26u8, 0xEBu8, 0x6Fu8, 0xA6u8, 0xACu8, 0xB0u8, 0xA6u8,
 ];
 
+/// Version ID of Catena-Butterfly without phi: Skein-512 of "Catena-Butterfly-Skein512".
+pub const BUTTERFLY_WITHOUT_PHI_VERSION_ID: [u8; NUM_KEY_BYTES] = [
+    0x5Cu8, 0xFAu8, 0x1Fu8, 0x35u8, 0x1Au8, 0x4Du8, 0x85u8, 0xF5u8,
+    0x29u8, 0x26u8, 0x40u8, 0x73u8, 0x10u8, 0x40u8, 0xF6u8, 0xA1u8,
+    0x5Fu8, 0x68u8, 0xCBu8, 0x96u8, 0x89u8, 0x44u8, 0x04u8, 0x1Eu8,
+    0xF5u8, 0x1Au8, 0x77u8, 0xA5u8, 0x8Au8, 0x56u8, 0xA6u8, 0xB7u8,
+    0x9Du8, 0xD8u8, 0xA2u8, 0xFEu8, 0x76u8, 0x17u8, 0xFFu8, 0x36u8,
+    0xE9u8, 0x22u8, 0xC6u8, 0x28u8, 0x8Du8, 0x13u8, 0x5Fu8, 0x4Eu8,
+    0xE6u8, 0xFDu8, 0x1Au8, 0x58u8, 0x7Bu8, 0x20u8, 0x0Fu8, 0xF2u8,
+    0x4Eu8, 0x2Au8, 0x9Eu8, 0x1Du8, 0x8Eu8, 0xBCu8, 0xB4u8, 0xFBu8,
+];
+
+/// Version ID of Catena-Butterfly with phi: Skein-512 of "Catena-Butterfly-Skein512-Phi".
+pub const BUTTERFLY_WITH_PHI_VERSION_ID: [u8; NUM_KEY_BYTES] = [
+    0x15u8, 0xEDu8, 0x79u8, 0xA5u8, 0x0Cu8, 0x3Cu8, 0x1Au8, 0x4Fu8,
+    0xB7u8, 0x84u8, 0x6Cu8, 0x91u8, 0xC4u8, 0x18u8, 0xC6u8, 0xEEu8,
+    0x70u8, 0x45u8, 0x6Au8, 0x24u8, 0x48u8, 0xB7u8, 0x17u8, 0xF3u8,
+    0x91u8, 0x39u8, 0xC6u8, 0x9Au8, 0x2Au8, 0xBCu8, 0x56u8, 0xE1u8,
+    0x81u8, 0x50u8, 0x9Cu8, 0x8Cu8, 0x29u8, 0x4Bu8, 0xB7u8, 0x26u8,
+    0x52u8, 0xA4u8, 0x27u8, 0xB6u8, 0xC8u8, 0xA1u8, 0x18u8, 0xD8u8,
+    0xC5u8, 0x27u8, 0x65u8, 0xEEu8, 0x43u8, 0x67u8, 0xCBu8, 0xDDu8,
+    0xC2u8, 0xB2u8, 0x3Fu8, 0x65u8, 0x02u8, 0x2Bu8, 0x2Au8, 0x26u8,
+];
+
 pub const NUM_HASH_BYTES: usize = NUM_HASH_WORDS * 8;
 
 pub const NUM_SALT_BYTES:     usize = 32;
 pub const NUM_SALT_WORDS:     usize = NUM_SALT_BYTES / 8;
-pub const MAX_PASSWORD_BYTES: usize = 125;
+pub const MIN_SALT_BYTES:     usize = 16;
+pub const MAX_SALT_BYTES:     usize = 64;
+/// The shortest stored hash verify_password() accepts; anything shorter is trivially guessable.
+pub const MIN_VERIFY_HASH_BYTES: usize = 16;
+/// The output length is bound into the tweak as 2 bytes.
+pub const MAX_OUTPUT_BYTES:   usize = u16::MAX as usize;
 pub const NUM_TWEAK_BYTES:    usize = {
     NUM_BLOCK_BYTES + 1 + 1 + 2 + 2
 };
 pub const NUM_RNG_BYTES: usize = NUM_BLOCK_BYTES + 16;
 
+/// The largest stored g_high verify_password() accepts: 1 GiB of graph memory for
+/// Catena-Dragonfly, 2 GiB for Catena-Butterfly. verify_password_with_limits() takes another.
+pub const DEFAULT_MAX_VERIFY_GARLIC: u8 = 24;
+
 pub const DOMAIN_PW_SCRAMBLER: u8 = 0u8;
 pub const DOMAIN_KDF:          u8 = 1u8;
 pub const DOMAIN_POW:          u8 = 2u8;
+/// Set in the domain byte of the tweak when associated data is bound, so that a hash with
+/// associated data never shares its input with one whose password begins with the
+/// associated data hash.
+pub const DOMAIN_FLAG_AD:      u8 = 0x80u8;
+/// The largest difficulty_bits pow_solve() and pow_verify() accept. Each nonce costs a full
+/// Catena evaluation and a solution takes 2^difficulty_bits of them on average, so a server
+/// must not be able to demand more than a client can actually compute.
+pub const MAX_POW_DIFFICULTY:  u32 = 32;
 
 pub const NUM_MHF_BYTES: usize = NUM_BLOCK_BYTES * 2;
 pub const NUM_MHF_WORDS: usize = NUM_MHF_BYTES / 8;
 
-pub const NUM_TWEAK_PW_SALT_BYTES: usize = {
-    NUM_TWEAK_BYTES + MAX_PASSWORD_BYTES + NUM_SALT_BYTES
-};
-
 pub const NUM_FLAP_BYTES:   usize = NUM_BLOCK_BYTES * 3;
 pub const NUM_FLAP_WORDS:   usize = NUM_FLAP_BYTES / 8;
 pub const NUM_PHI_BYTES:    usize = NUM_BLOCK_BYTES * 2;
@@ -105,9 +151,38 @@ pub const NUM_X_BYTES: usize = NUM_X_WORDS * 8;
 pub const NUM_HASH_INPUT_WORDS: usize = NUM_HASH_WORDS * 2;
 pub const NUM_HASH_INPUT_BYTES: usize = NUM_HASH_INPUT_WORDS * 8;
 
-pub const ERR_PW_TOO_LARGE:  i32 = 1;
-pub const ERR_ALLOC_FAIL:    i32 = 2;
-pub const ERR_ALREADY_INIT:  i32 = 3;
+/// Algorithm identifiers written into encoded password hash strings.
+pub const PHC_ID_DRAGONFLY: &str = "catena-dragonfly-skein512";
+pub const PHC_ID_BUTTERFLY: &str = "catena-butterfly-skein512";
+
+/// The graph Catena hashes over. Catena-Dragonfly uses the bit-reversal graph; Catena-Butterfly
+/// uses the double-butterfly graph, which resists time-memory tradeoffs better but is slower
+/// and needs twice the graph memory.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Graph {
+    #[default]
+    BitReversal,
+    DoubleButterfly,
+}
+
+impl Graph {
+    pub fn phc_id(self) -> &'static str {
+        match self {
+            Graph::BitReversal     => PHC_ID_DRAGONFLY,
+            Graph::DoubleButterfly => PHC_ID_BUTTERFLY,
+        }
+    }
+    /// The number of graph memory bytes needed for garlic @g_high.
+    fn num_graph_bytes(self, g_high: u8) -> Option<usize> {
+        let shift = match self {
+            Graph::BitReversal     => u32::from(g_high) + 6,
+            // Two rows of the butterfly are live at once.
+            Graph::DoubleButterfly => u32::from(g_high) + 7,
+        };
+        1usize.checked_shl(shift)
+    }
+}
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -129,7 +204,7 @@ pub union Temp {
     flap:          [u8; NUM_FLAP_BYTES],
     phi:           [u8; NUM_PHI_BYTES],
     mhf:           [u8; NUM_MHF_BYTES],
-    tweak_pw_salt: [u8; NUM_TWEAK_PW_SALT_BYTES],
+    tweak:         [u8; NUM_TWEAK_BYTES],
     catena:        [u8; NUM_CATENA_BYTES],
 }
 
@@ -139,15 +214,91 @@ impl Default for Temp {
     }
 }
 
+/// The server secret @key of keyed Catena, and the @user_id whose hash it encrypts.
+#[derive(Clone, Copy)]
+pub struct ServerKey<'a> {
+    pub key:     &'a [u8],
+    pub user_id: u64,
+}
+
+/// The cost parameters of one computation, as passed to get().
+#[derive(Clone, Copy)]
+struct Cost {
+    g_low:   u8,
+    lambda:  u8,
+    use_phi: bool,
+}
+
+/// Observes a long-running Catena computation. Between flap phases and garlic iterations the
+/// @cancel flag is polled, and @progress is called with the percent complete whenever it
+/// changes. Cancellation makes the computation fail with Error::Cancelled after wiping the
+/// graph memory and all intermediate state. Both are optional; Monitor::default() does nothing.
+#[derive(Clone, Copy, Default)]
+pub struct Monitor<'a> {
+    pub cancel:   Option<&'a AtomicBool>,
+    pub progress: Option<&'a (dyn Fn(u8) + Sync)>,
+}
+
+/// Tracks the work done by one computation against its @total, in units of 2^g hashes.
+struct Progress<'a> {
+    monitor:      &'a Monitor<'a>,
+    done:         u64,
+    total:        u64,
+    last_percent: u8,
+}
+
+impl<'a> Progress<'a> {
+    fn new(monitor: &'a Monitor<'a>, total: u64) -> Self {
+        Progress { monitor, done: 0, total: total.max(1), last_percent: 0 }
+    }
+    /// Account for @units more work, then poll the cancel flag and report progress.
+    fn advance(&mut self, units: u64) -> Result<(), Error> {
+        self.done = self.done.saturating_add(units).min(self.total);
+        if let Some(cancel) = self.monitor.cancel {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(Error::Cancelled);
+            }
+        }
+        if let Some(progress) = self.monitor.progress {
+            let percent = ((self.done as u128 * 100) / self.total as u128) as u8;
+            if percent != self.last_percent {
+                self.last_percent = percent;
+                progress(percent);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How Catena allocates its graph memory.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoryPolicy {
+    /// Allocate through SecureBuffer's secret memory map, which is locked into RAM and excluded
+    /// from core dumps. Falls back to a plain allocation if the map can't be made, e.g. when
+    /// the graph exceeds RLIMIT_MEMLOCK.
+    #[default]
+    Locked,
+    /// Always use a plain aligned allocation. Faster to set up for huge garlics, but the graph
+    /// may be swapped to disk.
+    Unlocked,
+}
+
 #[repr(C)]
-#[derive(Clone)]
 pub struct Catena {
     pub skein512:     Skein512,
     pub graph_memory: *mut u8,
+    pub graph_buffer: SecureBuffer,
+    pub memory_policy: MemoryPolicy,
     pub x:            [u8; NUM_X_BYTES],
     pub temp:         Temp,
-    pub salt:         [u8; NUM_SALT_BYTES],
+    pub salt:         [u8; MAX_SALT_BYTES],
+    pub salt_len:     usize,
+    /// Skein-512 hash of the associated data, if any was set.
+    pub ad_hash:      Option<[u8; NUM_HASH_BYTES]>,
     pub g_high:       u8,
+    /// Private: the graph memory was sized for it. Change it through set_graph().
+    graph:            Graph,
 }
 // SAFETY: @Catena is Send because the following conditions hold:
 //  - The @graph_memory pointer is owned by this Catena instance (not shared with other owners).
@@ -164,10 +315,15 @@ impl Default for Catena {
         Self {
             skein512: Skein512::default(),
             graph_memory: std::ptr::null_mut(),
+            graph_buffer: SecureBuffer::default(),
+            memory_policy: MemoryPolicy::default(),
             x: unsafe { std::mem::zeroed() },
             temp: Temp::default(),
             salt: unsafe { std::mem::zeroed() },
+            salt_len: NUM_SALT_BYTES,
+            ad_hash: None,
             g_high: 0u8,
+            graph: Graph::default(),
         }
     }
 }
@@ -179,114 +335,522 @@ impl Catena {
     }
     pub fn get_num_allocated_bytes(&self) -> usize {
         if self.is_initialized() {
-            1usize << {self.g_high + 6}
+            self.graph.num_graph_bytes(self.g_high).unwrap()
         } else {
             0usize
         }
     }
-    pub fn new_in_place(&mut self, g_high: u8) -> Result<(), i32> {
+    pub fn new_in_place(&mut self, g_high: u8) -> Result<(), Error> {
+        self.new_in_place_with_graph(g_high, Graph::BitReversal)
+    }
+    /// Allocate graph memory for @g_high and @graph according to @self.memory_policy.
+    pub fn new_in_place_with_graph(&mut self, g_high: u8, graph: Graph) -> Result<(), Error> {
         if self.is_initialized() {
-            return Err(ERR_ALREADY_INIT);
+            return Err(Error::AlreadyInit);
         }
-        let num_allocated_bytes = {1usize << {g_high + 6}};
-        let layout = std::alloc::Layout::from_size_align(num_allocated_bytes, NUM_BLOCK_BYTES).unwrap();
-        self.skein512 = Skein512::new();
-        self.graph_memory = unsafe {std::alloc::alloc(layout)};
-        if self.graph_memory.is_null() {
-            return Err(ERR_ALLOC_FAIL);
+        let num_allocated_bytes = Self::graph_size(g_high, graph)?;
+        match self.memory_policy {
+            MemoryPolicy::Locked   => SecureBuffer::new_in_place(&mut self.graph_buffer, num_allocated_bytes)?,
+            MemoryPolicy::Unlocked => SecureBuffer::new_unlocked_in_place(&mut self.graph_buffer, num_allocated_bytes)?,
         }
+        self.skein512 = Skein512::new();
+        self.graph_memory = self.graph_buffer.get_raw_ptr()?;
         self.x = [0u8; NUM_X_BYTES];
         self.temp = Temp::default();
-        self.salt = [0u8; NUM_SALT_BYTES];
+        self.salt = [0u8; MAX_SALT_BYTES];
+        self.salt_len = NUM_SALT_BYTES;
+        self.ad_hash = None;
         self.g_high = g_high;
+        self.graph = graph;
         Ok(())
     }
-    pub fn new(g_high: u8) -> Result<Catena, i32> {
-        let num_allocated_bytes = {1usize << {g_high + 6}};
-        let layout = std::alloc::Layout::from_size_align(num_allocated_bytes, NUM_BLOCK_BYTES).unwrap();
-        let graph_memory = unsafe {std::alloc::alloc(layout)};
-        if ! graph_memory.is_null() {
-            Ok(
-                Catena {
-                    skein512:     Skein512::new(),
-                    temp:         Temp::default(),
-                    x:            [0u8; NUM_X_BYTES],
-                    salt:         [0u8; NUM_SALT_BYTES],
-                    graph_memory,
-                    g_high
-                }
-            )
-        } else {
-            Err(ERR_ALLOC_FAIL)
+    pub fn new(g_high: u8) -> Result<Catena, Error> {
+        Self::new_with_graph(g_high, Graph::BitReversal)
+    }
+    pub fn new_with_graph(g_high: u8, graph: Graph) -> Result<Catena, Error> {
+        Self::new_with_memory_policy(g_high, graph, MemoryPolicy::Locked)
+    }
+    pub fn new_with_memory_policy(g_high: u8, graph: Graph, memory_policy: MemoryPolicy) -> Result<Catena, Error> {
+        let mut catena = Catena::default();
+        catena.memory_policy = memory_policy;
+        catena.new_in_place_with_graph(g_high, graph)?;
+        Ok(catena)
+    }
+    /// Make this instance usable with garlics up to @g_high, keeping its graph, memory policy,
+    /// salt and associated data. When the required graph size differs from the current allocation, the old memory
+    /// is zeroed and freed and new memory allocated; otherwise the allocation is reused.
+    /// An uninitialized instance is simply allocated.
+    pub fn reconfigure(&mut self, g_high: u8) -> Result<(), Error> {
+        let num_required_bytes = Self::graph_size(g_high, self.graph)?;
+        if self.is_initialized() {
+            if num_required_bytes == self.get_num_allocated_bytes() {
+                self.g_high = g_high;
+                return Ok(());
+            }
+            self.free_graph_memory();
+        }
+        let salt     = self.salt;
+        let salt_len = self.salt_len;
+        let ad_hash  = self.ad_hash;
+        self.new_in_place_with_graph(g_high, self.graph)?;
+        self.salt     = salt;
+        self.salt_len = salt_len;
+        self.ad_hash  = ad_hash;
+        Ok(())
+    }
+    pub fn get_graph(&self) -> Graph {
+        self.graph
+    }
+    /// Switch this instance to @graph, keeping its g_high, memory policy, salt and associated
+    /// data. The graph memory is zeroed, freed and allocated anew for @graph; should that
+    /// allocation fail, the instance is left uninitialized.
+    pub fn set_graph(&mut self, graph: Graph) -> Result<(), Error> {
+        if graph == self.graph {
+            return Ok(());
+        }
+        if ! self.is_initialized() {
+            self.graph = graph;
+            return Ok(());
+        }
+        let g_high = self.g_high;
+        self.free_graph_memory();
+        self.graph = graph;
+        self.reconfigure(g_high)
+    }
+    /// Zero the graph memory, all intermediate state, the salt and the associated data, keeping
+    /// the allocation. Afterwards nothing of the previous computation remains in the instance.
+    pub fn wipe(&mut self) {
+        self.wipe_state();
+        rssc::op::secure_zero(&mut self.salt);
+        self.salt_len = NUM_SALT_BYTES;
+        if let Some(ad_hash) = &mut self.ad_hash {
+            rssc::op::secure_zero(ad_hash);
+        }
+        self.ad_hash = None;
+    }
+    /// Whether the graph memory actually ended up in a locked secret memory map.
+    pub fn is_memory_locked(&self) -> bool {
+        self.is_initialized() && self.graph_buffer.get_tag() == TAG_MAP
+    }
+    /// Set the salt, which must be between MIN_SALT_BYTES and MAX_SALT_BYTES long.
+    pub fn set_salt(&mut self, salt: &[u8]) -> Result<(), Error> {
+        if salt.len() < MIN_SALT_BYTES || salt.len() > MAX_SALT_BYTES {
+            return Err(Error::BadParameter("salt must be between MIN_SALT_BYTES and MAX_SALT_BYTES long"));
         }
+        self.salt[..salt.len()].copy_from_slice(salt);
+        self.salt[salt.len()..].fill(0u8);
+        self.salt_len = salt.len();
+        Ok(())
+    }
+    pub fn get_salt(&self) -> &[u8] {
+        &self.salt[..self.salt_len]
+    }
+    /// Bind @associated_data, e.g. a username or service name, into every following hash.
+    /// Its Skein-512 hash is placed in the tweak after the salt length, as the Catena spec
+    /// describes, so the same password and salt give unrelated outputs under different
+    /// associated data. Its presence is also flagged in the domain byte of the tweak, so the
+    /// input stays unambiguous. Empty associated data clears it and leaves the tweak as it was
+    /// before associated data was supported, so existing hashes stay valid.
+    pub fn set_associated_data(&mut self, associated_data: &[u8]) {
+        self.ad_hash = if associated_data.is_empty() {
+            None
+        } else {
+            let mut ad_hash = [0u8; NUM_HASH_BYTES];
+            self.skein512.hash_native(&mut ad_hash, associated_data);
+            Some(ad_hash)
+        };
     }
+    /// Hash @password and fill all of @output. An @output of NUM_HASH_BYTES receives the Catena
+    /// output directly; any other length up to MAX_OUTPUT_BYTES receives a Skein-512 expansion
+    /// of it. The output length is bound into the tweak, so different lengths are unrelated.
     pub fn get(
         &mut self,
         output: &mut [u8],
         password: &[u8],
         g_low:   u8,
         lambda:  u8,
-        use_phi: bool) -> Result<(), i32>
+        use_phi: bool) -> Result<(), Error>
+    {
+        self.get_in_domain(output, password, Cost { g_low, lambda, use_phi }, DOMAIN_KDF, &Monitor::default())
+    }
+    /// get() with @associated_data in place of any set by set_associated_data().
+    pub fn get_with_ad(
+        &mut self,
+        output: &mut [u8],
+        password: &[u8],
+        associated_data: &[u8],
+        g_low:   u8,
+        lambda:  u8,
+        use_phi: bool) -> Result<(), Error>
+    {
+        let ad_hash = self.ad_hash;
+        self.set_associated_data(associated_data);
+        let result = self.get(output, password, g_low, lambda, use_phi);
+        self.ad_hash = ad_hash;
+        result
+    }
+    /// get() under a Monitor, which can observe progress and cancel the computation.
+    pub fn get_monitored(
+        &mut self,
+        output: &mut [u8],
+        password: &[u8],
+        g_low:   u8,
+        lambda:  u8,
+        use_phi: bool,
+        monitor: &Monitor) -> Result<(), Error>
+    {
+        self.get_in_domain(output, password, Cost { g_low, lambda, use_phi }, DOMAIN_KDF, monitor)
+    }
+    /// Client half of Catena's server-relief protocol: do all of the memory-hard work of get()
+    /// and write the value that precedes the final hash into @output. The server completes it
+    /// with server_finish(), which needs no graph memory. Only NUM_HASH_BYTES outputs are
+    /// supported.
+    pub fn client_relief(
+        &mut self,
+        output: &mut [u8],
+        password: &[u8],
+        g_low:   u8,
+        lambda:  u8,
+        use_phi: bool) -> Result<(), Error>
+    {
+        if output.len() != NUM_HASH_BYTES {
+            return Err(Error::BadParameter("output must be NUM_HASH_BYTES long"));
+        }
+        self.get_pre_final(NUM_HASH_BYTES, password, Cost { g_low, lambda, use_phi }, DOMAIN_KDF, &Monitor::default())?;
+        output.copy_from_slice(&self.x);
+        Ok(())
+    }
+    /// Server half of Catena's server-relief protocol: apply the final hash to @pre_final, the
+    /// output of client_relief() from an instance allocated with @g_high. The result written to
+    /// @output equals that of get().
+    pub fn server_finish(
+        output: &mut [u8],
+        pre_final: &[u8],
+        g_high: u8) -> Result<(), Error>
+    {
+        if pre_final.len() != NUM_HASH_BYTES {
+            return Err(Error::BadParameter("pre_final must be NUM_HASH_BYTES long"));
+        }
+        if output.len() != NUM_HASH_BYTES {
+            return Err(Error::BadParameter("output must be NUM_HASH_BYTES long"));
+        }
+        let mut skein512 = Skein512::new();
+        let mut catena = [0u8; NUM_CATENA_BYTES];
+        catena[0] = g_high;
+        catena[1..].copy_from_slice(pre_final);
+        hash_native!(&mut skein512.ubi512, output, &catena);
+        rssc::op::secure_zero(&mut catena);
+        Ok(())
+    }
+    /// Keyed Catena: compute get() and encrypt the result under @server_key, bound to its user
+    /// and this instance's g_high. Without the key the stored value is useless for password
+    /// guessing.
+    pub fn get_keyed(
+        &mut self,
+        output: &mut [u8],
+        password: &[u8],
+        server_key: &ServerKey,
+        g_low:   u8,
+        lambda:  u8,
+        use_phi: bool) -> Result<(), Error>
+    {
+        self.get(output, password, g_low, lambda, use_phi)?;
+        Self::apply_key(output, server_key.key, server_key.user_id, self.g_high)
+    }
+    /// XOR @output with the keyed-Catena mask H(@key || @user_id || @g_high || @key), where
+    /// @user_id is encoded as 8 little-endian bytes and H outputs as many bytes as @output holds.
+    /// Applying it twice is the identity, so this both encrypts and decrypts; decrypt before
+    /// upgrade() and encrypt again after.
+    pub fn apply_key(
+        output: &mut [u8],
+        key:     &[u8],
+        user_id: u64,
+        g_high:  u8) -> Result<(), Error>
+    {
+        if output.is_empty() || output.len() > MAX_OUTPUT_BYTES {
+            return Err(Error::BadParameter("output must be between 1 and MAX_OUTPUT_BYTES long"));
+        }
+        let mut mask = vec![0u8; output.len()];
+        let mut skein512 = Skein512::new();
+        {
+            let ubi = &mut skein512.ubi512;
+            ubi.threefish512.key.fill(0u64);
+            ubi.chain_config({output.len() as u64} * 8u64);
+            ubi.chain_message_parts(&[key, &user_id.to_le_bytes(), &[g_high], key]);
+            ubi.chain_output(&mut mask);
+        }
+        for (o, m) in output.iter_mut().zip(mask.iter()) {
+            *o ^= *m;
+        }
+        rssc::op::secure_zero(&mut mask);
+        Ok(())
+    }
+    /// Re-encrypt the keyed output @stored from @old_key to @new_key without the password.
+    pub fn rekey(
+        stored:  &mut [u8],
+        old_key: &[u8],
+        new_key: &[u8],
+        user_id: u64,
+        g_high:  u8) -> Result<(), Error>
+    {
+        Self::apply_key(stored, old_key, user_id, g_high)?;
+        Self::apply_key(stored, new_key, user_id, g_high)
+    }
+    /// Raise the garlic of @stored_output, the output of get() with g_high = @old_g_high,
+    /// to @new_g_high without knowing the password. The result written to @output equals what
+    /// get() would produce with g_high = @new_g_high.
+    /// @self.salt must hold the salt the hash was made with, and @self must have been allocated
+    /// with a g_high of at least @new_g_high. @lambda and @use_phi must match the originals.
+    /// Only NUM_HASH_BYTES outputs can be upgraded; expanded outputs can't be inverted.
+    pub fn upgrade(
+        &mut self,
+        output: &mut [u8],
+        stored_output: &[u8],
+        old_g_high: u8,
+        new_g_high: u8,
+        lambda:  u8,
+        use_phi: bool) -> Result<(), Error>
+    {
+        if ! self.is_initialized() {
+            return Err(Error::NotInit);
+        }
+        if stored_output.len() != NUM_HASH_BYTES {
+            return Err(Error::BadParameter("stored_output must be NUM_HASH_BYTES long"));
+        }
+        if output.len() != NUM_HASH_BYTES {
+            return Err(Error::BadParameter("output must be NUM_HASH_BYTES long"));
+        }
+        if old_g_high == 0 {
+            return Err(Error::BadParameter("old_g_high must be at least 1"));
+        }
+        if old_g_high > new_g_high {
+            return Err(Error::BadParameter("new_g_high must not be less than old_g_high"));
+        }
+        if new_g_high > self.g_high {
+            return Err(Error::BadParameter("new_g_high exceeds the allocated g_high"));
+        }
+        self.x.copy_from_slice(stored_output);
+        let monitor = Monitor::default();
+        let mut progress = Progress::new(&monitor, 0);
+        self.garlic_loop(old_g_high + 1, new_g_high, lambda, use_phi, &mut progress)?;
+        output.copy_from_slice(&self.x);
+        Ok(())
+    }
+    /// Memory-hard client puzzle: find the smallest nonce whose Catena output, computed under
+    /// @challenge with @params, begins with @difficulty_bits zero bits, at most
+    /// MAX_POW_DIFFICULTY. The tweak carries DOMAIN_POW, so puzzle outputs can never collide
+    /// with password hashes.
+    pub fn pow_solve(
+        challenge: &[u8],
+        difficulty_bits: u32,
+        params: &Params) -> Result<u64, Error>
+    {
+        Self::pow_solve_monitored(challenge, difficulty_bits, params, &Monitor::default())
+    }
+    /// pow_solve() that fails with Error::Cancelled once @monitor.cancel is set, checked while
+    /// evaluating every nonce. Progress is not reported, as the number of nonces is unknown.
+    pub fn pow_solve_monitored(
+        challenge: &[u8],
+        difficulty_bits: u32,
+        params: &Params,
+        monitor: &Monitor) -> Result<u64, Error>
+    {
+        let mut catena = Self::new_pow(challenge, difficulty_bits, params)?;
+        let mut output = [0u8; NUM_HASH_BYTES];
+        let monitor = Monitor { cancel: monitor.cancel, progress: None };
+        for nonce in 0u64..=u64::MAX {
+            catena.get_in_domain(&mut output, &nonce.to_le_bytes(), params.cost(), DOMAIN_POW, &monitor)?;
+            if leading_zero_bits(&output) >= difficulty_bits {
+                return Ok(nonce);
+            }
+        }
+        Err(Error::BadParameter("no nonce satisfies difficulty_bits"))
+    }
+    /// Check a nonce returned by pow_solve(). Costs one Catena evaluation.
+    pub fn pow_verify(
+        challenge: &[u8],
+        nonce: u64,
+        difficulty_bits: u32,
+        params: &Params) -> Result<bool, Error>
+    {
+        let mut catena = Self::new_pow(challenge, difficulty_bits, params)?;
+        let mut output = [0u8; NUM_HASH_BYTES];
+        catena.get_in_domain(&mut output, &nonce.to_le_bytes(), params.cost(), DOMAIN_POW, &Monitor::default())?;
+        Ok(leading_zero_bits(&output) >= difficulty_bits)
+    }
+    // PRIVATE IMPLEMENTATION.
+    /// Allocate a Catena for @params whose salt is the 32-byte Skein-512 hash of @challenge.
+    fn new_pow(challenge: &[u8], difficulty_bits: u32, params: &Params) -> Result<Catena, Error> {
+        if difficulty_bits > MAX_POW_DIFFICULTY {
+            return Err(Error::BadParameter("difficulty_bits must not exceed MAX_POW_DIFFICULTY"));
+        }
+        params.validate()?;
+        let mut catena = Catena::new_with_graph(params.g_high, params.graph)?;
+        catena.skein512.hash(&mut catena.salt[..NUM_SALT_BYTES], challenge);
+        catena.salt_len = NUM_SALT_BYTES;
+        Ok(catena)
+    }
+    fn get_in_domain(
+        &mut self,
+        output: &mut [u8],
+        password: &[u8],
+        cost:    Cost,
+        domain:  u8,
+        monitor: &Monitor) -> Result<(), Error>
+    {
+        self.get_pre_final(output.len(), password, cost, domain, monitor)?;
+        self.hash_garlic(self.g_high);
+        if output.len() == NUM_HASH_BYTES {
+            output.copy_from_slice(&self.x);
+        } else {
+            self.skein512.hash(output, &self.x);
+        }
+        Ok(())
+    }
+    /// Zero the graph memory and the intermediate state of a computation: the X buffer, the
+    /// temporaries and the Skein-512 chaining state, key schedule and message buffer.
+    fn wipe_state(&mut self) {
+        let num_allocated_bytes = self.get_num_allocated_bytes();
+        if num_allocated_bytes > 0 {
+            let gm = unsafe {std::slice::from_raw_parts_mut(self.graph_memory, num_allocated_bytes)};
+            rssc::op::secure_zero(gm);
+        }
+        rssc::op::secure_zero(&mut self.x);
+        rssc::op::secure_zero(std::slice::from_mut(&mut self.temp));
+        rssc::op::secure_zero(std::slice::from_mut(&mut self.skein512));
+    }
+    /// Zero and release the graph memory, leaving the instance uninitialized.
+    fn free_graph_memory(&mut self) {
+        self.wipe_state();
+        self.graph_buffer.nullify();
+        self.graph_memory = std::ptr::null_mut();
+    }
+    fn graph_size(g_high: u8, graph: Graph) -> Result<usize, Error> {
+        if g_high == 0 {
+            return Err(Error::BadParameter("g_high must be at least 1"));
+        }
+        match graph.num_graph_bytes(g_high) {
+            Some(n) if n <= isize::MAX as usize => Ok(n),
+            _ => Err(Error::BadParameter("g_high is too large to address"))
+        }
+    }
+    /// Compute everything up to, but excluding, the final hash of get() into @self.x.
+    fn get_pre_final(
+        &mut self,
+        output_len: usize,
+        password: &[u8],
+        cost:    Cost,
+        domain:  u8,
+        monitor: &Monitor) -> Result<(), Error>
     {
-        if password.len() > MAX_PASSWORD_BYTES {
-            return Err(ERR_PW_TOO_LARGE);
+        let Cost { g_low, lambda, use_phi } = cost;
+        if ! self.is_initialized() {
+            return Err(Error::NotInit);
+        }
+        if g_low == 0 {
+            return Err(Error::BadParameter("g_low must be at least 1"));
+        }
+        if output_len == 0 || output_len > MAX_OUTPUT_BYTES {
+            return Err(Error::BadParameter("output must be between 1 and MAX_OUTPUT_BYTES long"));
+        }
+        if self.salt_len < MIN_SALT_BYTES || self.salt_len > MAX_SALT_BYTES {
+            return Err(Error::BadParameter("salt must be between MIN_SALT_BYTES and MAX_SALT_BYTES long"));
         }
         let g_low = if g_low > self.g_high {
             self.g_high
         } else {
             g_low
         };
-        self.make_tweak(lambda, use_phi);
+        self.make_tweak(lambda, use_phi, output_len, domain);
         {
+            // Stream the tweak, password and salt through UBI; the password may be any length.
             let ubi = &mut self.skein512.ubi512;
-            let salt_offset = NUM_TWEAK_BYTES + password.len();
-            let tps = unsafe {&mut self.temp.tweak_pw_salt};
-            tps[NUM_TWEAK_BYTES..salt_offset].copy_from_slice(
-                &password
-            );
-            tps[salt_offset..salt_offset + NUM_SALT_BYTES].copy_from_slice(
-                &self.salt
-            );
-            hash_native!(ubi, &mut self.x, &tps[..salt_offset + NUM_SALT_BYTES]);
+            let ad_hash: &[u8] = match &self.ad_hash {
+                Some(h) => h,
+                None    => &[],
+            };
+            ubi.threefish512.key[..NUM_KEY_WORDS].copy_from_slice(&skein512::NATIVE_INIT);
+            ubi.chain_message_parts(&[unsafe {&self.temp.tweak}, ad_hash, password, &self.salt[..self.salt_len]]);
+            ubi.chain_output_native(&mut self.x);
+        }
+        let g_init = g_low.div_ceil(2);
+        let mut total = self.flap_cost(g_init, lambda, use_phi);
+        for g in g_low..=self.g_high {
+            total = total.saturating_add(self.flap_cost(g, lambda, use_phi));
         }
+        let mut progress = Progress::new(monitor, total);
+        let result = self.run_garlics(g_init, g_low, lambda, use_phi, &mut progress);
+        if result.is_err() {
+            self.wipe_state();
+        }
+        result
+    }
+    fn run_garlics(&mut self, g_init: u8, g_low: u8, lambda: u8, use_phi: bool, progress: &mut Progress) -> Result<(), Error> {
         // Initial flap.
-        self.flap((g_low + 1) / 2, lambda, use_phi);
+        self.flap(g_init, lambda, use_phi, progress)?;
         // Hash the X buffer into itself.
         hash_native!(&mut self.skein512.ubi512, &mut self.x, &self.x);
-        // Iterate over the garlics with g, from g_low to g_high.
-        for g in g_low..=self.g_high {
-            self.flap(g, lambda, use_phi);
-            unsafe {
-                *self.temp.catena.get_unchecked_mut(0) = g;
-                self.temp.catena[1..].copy_from_slice(&self.x);
-            }
-            hash_native!(&mut self.skein512.ubi512, &mut self.x, unsafe {&self.temp.catena});
+        // Iterate over the garlics with g, from g_low to g_high, stopping short of the last hash.
+        self.garlic_loop(g_low, self.g_high - 1, lambda, use_phi, progress)?;
+        self.flap(self.g_high, lambda, use_phi, progress)
+    }
+    /// For each garlic g from @g_from to @g_to inclusive, flap @self.x and hash it with g prepended.
+    fn garlic_loop(&mut self, g_from: u8, g_to: u8, lambda: u8, use_phi: bool, progress: &mut Progress) -> Result<(), Error> {
+        for g in g_from..=g_to {
+            self.flap(g, lambda, use_phi, progress)?;
+            self.hash_garlic(g);
         }
-        output[..NUM_HASH_BYTES].copy_from_slice(&self.x);
         Ok(())
     }
-    // PRIVATE IMPLEMENTATION.
-    fn make_tweak(&mut self, lambda: u8, use_phi: bool) {
-        let version_id = if use_phi {
-            &WITH_PHI_VERSION_ID
-        } else {
-            &WITHOUT_PHI_VERSION_ID
+    /// The approximate cost of flap(@garlic) in units of 2^@garlic hashes, split into the
+    /// graph fill, the graph hashing and phi.
+    fn flap_phase_costs(&self, garlic: u8, lambda: u8, use_phi: bool) -> [u64; 3] {
+        let unit = 1u64.checked_shl(u32::from(garlic)).unwrap_or(u64::MAX);
+        let rounds = match self.graph {
+            Graph::BitReversal     => u64::from(lambda),
+            Graph::DoubleButterfly => u64::from(lambda) * (2 * u64::from(garlic)).saturating_sub(1),
+        };
+        [unit, unit.saturating_mul(rounds), if use_phi { unit } else { 0 }]
+    }
+    fn flap_cost(&self, garlic: u8, lambda: u8, use_phi: bool) -> u64 {
+        self.flap_phase_costs(garlic, lambda, use_phi).iter().fold(0u64, |a, &b| a.saturating_add(b))
+    }
+    /// Hash @self.x into itself with the garlic @g prepended.
+    fn hash_garlic(&mut self, g: u8) {
+        unsafe {
+            *self.temp.catena.get_unchecked_mut(0) = g;
+            self.temp.catena[1..].copy_from_slice(&self.x);
+        }
+        hash_native!(&mut self.skein512.ubi512, &mut self.x, unsafe {&self.temp.catena});
+    }
+    fn make_tweak(&mut self, lambda: u8, use_phi: bool, output_len: usize, domain: u8) {
+        let version_id = match (self.graph, use_phi) {
+            (Graph::BitReversal,     true)  => &WITH_PHI_VERSION_ID,
+            (Graph::BitReversal,     false) => &WITHOUT_PHI_VERSION_ID,
+            (Graph::DoubleButterfly, true)  => &BUTTERFLY_WITH_PHI_VERSION_ID,
+            (Graph::DoubleButterfly, false) => &BUTTERFLY_WITHOUT_PHI_VERSION_ID,
         };
-        unsafe { self.temp.tweak_pw_salt[..NUM_KEY_BYTES].copy_from_slice(version_id) };
+        unsafe { self.temp.tweak[..NUM_KEY_BYTES].copy_from_slice(version_id) };
         let mut i = NUM_KEY_BYTES;
-        unsafe {*self.temp.tweak_pw_salt.get_unchecked_mut(i) = DOMAIN_KDF};
+        let domain = if self.ad_hash.is_some() {
+            domain | DOMAIN_FLAG_AD
+        } else {
+            domain
+        };
+        unsafe {*self.temp.tweak.get_unchecked_mut(i) = domain};
         i += 1;
-        unsafe {*self.temp.tweak_pw_salt.get_unchecked_mut(i) = lambda};
+        unsafe {*self.temp.tweak.get_unchecked_mut(i) = lambda};
         i += 1;
-        unsafe {*self.temp.tweak_pw_salt.get_unchecked_mut(i) = NUM_BLOCK_BYTES as u8};
+        unsafe {*self.temp.tweak.get_unchecked_mut(i) = output_len as u8};
         i += 1;
-        unsafe {*self.temp.tweak_pw_salt.get_unchecked_mut(i) = (NUM_BLOCK_BYTES >> 8) as u8};
+        unsafe {*self.temp.tweak.get_unchecked_mut(i) = (output_len >> 8) as u8};
         i += 1;
-        unsafe {*self.temp.tweak_pw_salt.get_unchecked_mut(i) = NUM_SALT_BYTES as u8};
+        unsafe {*self.temp.tweak.get_unchecked_mut(i) = self.salt_len as u8};
         i += 1;
-        unsafe {*self.temp.tweak_pw_salt.get_unchecked_mut(i) = (NUM_SALT_BYTES >> 8) as u8};
+        unsafe {*self.temp.tweak.get_unchecked_mut(i) = (self.salt_len >> 8) as u8};
     }
-    fn flap(&mut self, garlic: u8, lambda: u8, use_phi: bool) {
+    fn flap(&mut self, garlic: u8, lambda: u8, use_phi: bool, progress: &mut Progress) -> Result<(), Error> {
+        let [fill_cost, hash_cost, phi_cost] = self.flap_phase_costs(garlic, lambda, use_phi);
         let num_allocated_bytes = self.get_num_allocated_bytes();
         let ubi = &mut self.skein512.ubi512;
         let flap = unsafe{ &mut self.temp.flap };
@@ -347,14 +911,21 @@ impl Catena {
             i = next;
         }
         self.gamma(garlic);
-        self.graph_hash(garlic, lambda);
+        progress.advance(fill_cost)?;
+        match self.graph {
+            Graph::BitReversal     => self.graph_hash(garlic, lambda),
+            Graph::DoubleButterfly => self.butterfly_hash(garlic, lambda),
+        }
+        progress.advance(hash_cost)?;
         if use_phi {
             self.phi(garlic);
+            progress.advance(phi_cost)?;
         } else {
             self.x.copy_from_slice(
                 &graph_memory[idx!(max_hash_index)..idx!(max_hash_index + 1)]
             );
         }
+        Ok(())
     }// ~ fn flap()
     fn gamma(&mut self, garlic: u8) {
         const RNG_CONFIG: [u64; NUM_KEY_WORDS] = [
@@ -374,9 +945,10 @@ impl Catena {
         let num_allocated_bytes = self.get_num_allocated_bytes();
         let ubi = &mut self.skein512.ubi512;
         let mem = unsafe { &mut self.temp.gamma };
-        mem.rng[..NUM_SALT_BYTES].copy_from_slice(&self.salt);
-        unsafe { *mem.rng.get_unchecked_mut(NUM_SALT_BYTES) = garlic; }
-        hash_native!(ubi, &mut mem.rng[..NUM_HASH_BYTES], &mem.rng[..NUM_SALT_BYTES + 1]);
+        let salt_len = self.salt_len;
+        mem.rng[..salt_len].copy_from_slice(&self.salt[..salt_len]);
+        unsafe { *mem.rng.get_unchecked_mut(salt_len) = garlic; }
+        hash_native!(ubi, &mut mem.rng[..NUM_HASH_BYTES], &mem.rng[..salt_len + 1]);
         let graph_memory = unsafe {std::slice::from_raw_parts_mut(self.graph_memory, num_allocated_bytes)};
         let count  = 1u64 << (((3 * garlic) + 3) / 4);
         let rshift = 64 - garlic;
@@ -422,6 +994,61 @@ impl Catena {
             }
         }
     }
+    /// Index of the vertex of layer @i+1 that the double-butterfly graph connects vertex @j of
+    /// layer @i to, for layers 0 through 2*@garlic - 2.
+    fn sigma(garlic: u8, i: u64, j: u64) -> u64 {
+        let g = garlic as u64;
+        if i < g {
+            j ^ (1u64 << (g - 1 - i))
+        } else {
+            j ^ (1u64 << (i - (g - 1)))
+        }
+    }
+    /// Double-butterfly hashing. Each of the 2*@garlic - 1 layers is computed from the previous
+    /// one, with the two live layers alternating between the two halves of the graph memory.
+    /// The final layer always ends up in the first half.
+    fn butterfly_hash(&mut self, garlic: u8, lambda: u8) {
+        let num_allocated_bytes = self.get_num_allocated_bytes();
+        let ubi = &mut self.skein512.ubi512;
+        let mhf = unsafe {&mut self.temp.mhf};
+        let c = 1u64 << garlic;
+        let num_layers = (2 * garlic as u64) - 1;
+        let graph_memory = unsafe {std::slice::from_raw_parts_mut(self.graph_memory, num_allocated_bytes)};
+        let xor_into = |dest: &mut [u8], a: &[u8], b: &[u8]| {
+            for ((d, x), y) in dest.iter_mut().zip(a.iter()).zip(b.iter()) {
+                *d = *x ^ *y;
+            }
+        };
+
+        let mut src = 0u64;
+        let mut dst = c;
+        for _k in 0u8..lambda {
+            for i in 0u64..num_layers {
+                xor_into(
+                    &mut mhf[idx!(0)..idx!(1)],
+                    &graph_memory[idx!(src + c - 1)..idx!(src + c)],
+                    &graph_memory[idx!(src)..idx!(src + 1)]
+                );
+                let s = src + Self::sigma(garlic, i, 0);
+                mhf[idx!(1)..idx!(2)].copy_from_slice(&graph_memory[idx!(s)..idx!(s + 1)]);
+                hash_native!(ubi, &mut graph_memory[idx!(dst)..idx!(dst + 1)], &mhf[idx!(0)..idx!(2)]);
+                for j in 1u64..c {
+                    xor_into(
+                        &mut mhf[idx!(0)..idx!(1)],
+                        &graph_memory[idx!(dst + j - 1)..idx!(dst + j)],
+                        &graph_memory[idx!(src + j)..idx!(src + j + 1)]
+                    );
+                    let s = src + Self::sigma(garlic, i, j);
+                    mhf[idx!(1)..idx!(2)].copy_from_slice(&graph_memory[idx!(s)..idx!(s + 1)]);
+                    hash_native!(ubi, &mut graph_memory[idx!(dst + j)..idx!(dst + j + 1)], &mhf[idx!(0)..idx!(2)]);
+                }
+                std::mem::swap(&mut src, &mut dst);
+            }
+        }
+        if src != 0 {
+            graph_memory.copy_within(idx!(c)..idx!(2 * c), 0);
+        }
+    }
     fn phi(&mut self, garlic: u8) {
         let num_allocated_bytes = self.get_num_allocated_bytes();
         let ubi = &mut self.skein512.ubi512;
@@ -447,17 +1074,800 @@ impl Catena {
 
 impl Drop for Catena {
     fn drop(&mut self) {
-        let num_allocated_bytes = self.get_num_allocated_bytes();
-        if self.is_initialized() && num_allocated_bytes > 0 {
-            {
-                let gm = unsafe {std::slice::from_raw_parts_mut(self.graph_memory, num_allocated_bytes)};
-                rssc::op::secure_zero(gm);
-            }
-            let layout = std::alloc::Layout::from_size_align(num_allocated_bytes, NUM_BLOCK_BYTES).unwrap();
-            unsafe {std::alloc::dealloc(self.graph_memory, layout)}
-        }
+        // Release the graph memory before zeroing over the SecureBuffer that tracks it.
+        self.free_graph_memory();
         let m = unsafe {std::slice::from_raw_parts_mut(self as *mut _ as *mut u8, std::mem::size_of::<Self>())};
         rssc::op::secure_zero(m);
     }
 }
 
+
+/// A fixed set of pre-allocated Catena instances shared between worker threads. Instances are
+/// wiped when returned; callers may reconfigure() an acquired instance for another cost tier.
+pub struct CatenaPool {
+    instances: Mutex<Vec<Catena>>,
+    available: Condvar,
+}
+
+/// A Catena borrowed from a CatenaPool. It is wiped and returned to the pool when dropped.
+pub struct PooledCatena<'a> {
+    pool:   &'a CatenaPool,
+    catena: Option<Catena>,
+}
+
+impl CatenaPool {
+    pub fn new(count: usize, g_high: u8, graph: Graph) -> Result<CatenaPool, Error> {
+        if count == 0 {
+            return Err(Error::BadParameter("count must be at least 1"));
+        }
+        let mut instances = Vec::with_capacity(count);
+        for _ in 0..count {
+            instances.push(Catena::new_with_graph(g_high, graph)?);
+        }
+        Ok(CatenaPool {
+            instances: Mutex::new(instances),
+            available: Condvar::new(),
+        })
+    }
+    /// Take an instance, blocking until one is available.
+    pub fn acquire(&self) -> PooledCatena<'_> {
+        let mut instances = self.instances.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(catena) = instances.pop() {
+                return PooledCatena { pool: self, catena: Some(catena) };
+            }
+            instances = self.available.wait(instances).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+    /// Take an instance if one is available right now.
+    pub fn try_acquire(&self) -> Option<PooledCatena<'_>> {
+        let mut instances = self.instances.lock().unwrap_or_else(|e| e.into_inner());
+        instances.pop().map(|catena| PooledCatena { pool: self, catena: Some(catena) })
+    }
+    /// The number of instances currently available.
+    pub fn num_available(&self) -> usize {
+        self.instances.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+impl std::ops::Deref for PooledCatena<'_> {
+    type Target = Catena;
+    fn deref(&self) -> &Catena {
+        self.catena.as_ref().unwrap()
+    }
+}
+
+impl std::ops::DerefMut for PooledCatena<'_> {
+    fn deref_mut(&mut self) -> &mut Catena {
+        self.catena.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledCatena<'_> {
+    fn drop(&mut self) {
+        if let Some(mut catena) = self.catena.take() {
+            catena.wipe();
+            self.pool.instances.lock().unwrap_or_else(|e| e.into_inner()).push(catena);
+            self.pool.available.notify_one();
+        }
+    }
+}
+
+/// The cost parameters of a Catena password hash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Params {
+    pub g_low:   u8,
+    pub g_high:  u8,
+    pub lambda:  u8,
+    pub use_phi: bool,
+    pub graph:   Graph,
+}
+
+impl Params {
+    fn cost(&self) -> Cost {
+        Cost { g_low: self.g_low, lambda: self.lambda, use_phi: self.use_phi }
+    }
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.g_low == 0 {
+            return Err(Error::BadParameter("g_low must be at least 1"));
+        }
+        if self.g_low > self.g_high {
+            return Err(Error::BadParameter("g_low must not exceed g_high"));
+        }
+        if self.lambda == 0 {
+            return Err(Error::BadParameter("lambda must be at least 1"));
+        }
+        Ok(())
+    }
+    /// The graph memory a Catena allocated for these parameters holds; the same value
+    /// get_num_allocated_bytes() reports. Saturates at usize::MAX for unaddressable garlics.
+    pub fn get_num_allocated_bytes(&self) -> usize {
+        self.graph.num_graph_bytes(self.g_high).unwrap_or(usize::MAX)
+    }
+}
+
+/// The largest lambda calibrate() will choose.
+pub const MAX_CALIBRATED_LAMBDA: u8 = 8;
+
+/// Benchmark Catena-Dragonfly on this machine and pick parameters for hashing within about
+/// @target_duration using no more than @max_memory_bytes of graph memory: the largest garlic
+/// that fits both budgets, then the largest lambda (up to MAX_CALIBRATED_LAMBDA) that still fits
+/// the time budget. g_low is set equal to g_high.
+pub fn calibrate(
+    target_duration: std::time::Duration,
+    max_memory_bytes: usize,
+    use_phi: bool) -> Result<Params, Error>
+{
+    const BENCH_GARLIC: u8 = 12;
+    let graph = Graph::BitReversal;
+    let fits_memory = |g: u8| graph.num_graph_bytes(g).is_some_and(|n| n <= max_memory_bytes);
+    if ! fits_memory(1) {
+        return Err(Error::BadParameter("max_memory_bytes is too small for any garlic"));
+    }
+    let mut g_max = 1u8;
+    while g_max < 62 && fits_memory(g_max + 1) {
+        g_max += 1;
+    }
+
+    // Time one garlic's worth of hashing at lambda 1 and 2; take the best of a few runs.
+    let g_bench = BENCH_GARLIC.min(g_max);
+    let mut catena = Catena::new_with_graph(g_bench, graph)?;
+    let mut output = [0u8; NUM_HASH_BYTES];
+    let mut bench = |lambda: u8| -> Result<f64, Error> {
+        let mut best = f64::MAX;
+        for _ in 0..3 {
+            let start = std::time::Instant::now();
+            catena.get(&mut output, b"calibrate", g_bench, lambda, use_phi)?;
+            best = best.min(start.elapsed().as_secs_f64());
+        }
+        Ok(best)
+    };
+    let t1 = bench(1)?;
+    let t2 = bench(2)?;
+    // Model the time as 2^g * (fixed + per_lambda * lambda) seconds.
+    let blocks = (1u64 << g_bench) as f64;
+    let per_lambda = ((t2 - t1) / blocks).max(f64::MIN_POSITIVE);
+    let fixed = ((t1 / blocks) - per_lambda).max(0.0);
+    let predict = |g: u8, lambda: u8| ((1u64 << g) as f64) * (fixed + (per_lambda * lambda as f64));
+
+    let target = target_duration.as_secs_f64();
+    let mut g_high = 1u8;
+    while g_high < g_max && predict(g_high + 1, 1) <= target {
+        g_high += 1;
+    }
+    let mut lambda = 1u8;
+    while lambda < MAX_CALIBRATED_LAMBDA && predict(g_high, lambda + 1) <= target {
+        lambda += 1;
+    }
+    Ok(Params { g_low: g_high, g_high, lambda, use_phi, graph })
+}
+
+/// Hash @password under freshly generated salt and return a self-describing string of the form
+/// `$catena-dragonfly-skein512$g=<g_high>,gl=<g_low>,l=<lambda>,phi=<0|1>$<salt>$<hash>`,
+/// with the salt and hash in unpadded Base64. Catena-Butterfly hashes use
+/// `catena-butterfly-skein512` as the identifier instead.
+pub fn hash_password(password: &[u8], params: &Params) -> Result<String, Error> {
+    hash_password_with_ad(password, &[], params)
+}
+
+/// hash_password() bound to @associated_data, which is not stored in the encoded string and
+/// must be passed again to verify_password_with_ad().
+pub fn hash_password_with_ad(password: &[u8], associated_data: &[u8], params: &Params) -> Result<String, Error> {
+    hash_password_monitored(password, associated_data, params, &Monitor::default())
+}
+
+fn hash_password_monitored(
+    password: &[u8],
+    associated_data: &[u8],
+    params: &Params,
+    monitor: &Monitor) -> Result<String, Error>
+{
+    params.validate()?;
+    let mut catena = Catena::new_with_graph(params.g_high, params.graph)?;
+    catena.set_associated_data(associated_data);
+    Csprng::new().get_bytes(&mut catena.salt[..NUM_SALT_BYTES]);
+    let mut output = [0u8; NUM_HASH_BYTES];
+    catena.get_monitored(&mut output, password, params.g_low, params.lambda, params.use_phi, monitor)?;
+    let encoded = encode_hash(params, catena.get_salt(), &output);
+    rssc::op::secure_zero(&mut output);
+    Ok(encoded)
+}
+
+/// Recompute the hash of @password with the parameters and salt stored in @encoded and compare
+/// it with the stored hash in constant time. Malformed strings are an error; a wrong password
+/// is Ok(false). Salts of MIN_SALT_BYTES to MAX_SALT_BYTES and hashes of
+/// MIN_VERIFY_HASH_BYTES to MAX_OUTPUT_BYTES are accepted.
+/// Stored garlics above DEFAULT_MAX_VERIFY_GARLIC are rejected before any memory is allocated.
+pub fn verify_password(password: &[u8], encoded: &str) -> Result<bool, Error> {
+    verify_password_with_ad(password, &[], encoded)
+}
+
+/// verify_password() for a hash made by hash_password_with_ad() with @associated_data.
+pub fn verify_password_with_ad(password: &[u8], associated_data: &[u8], encoded: &str) -> Result<bool, Error> {
+    verify_password_with_limits(password, associated_data, encoded, DEFAULT_MAX_VERIFY_GARLIC)
+}
+
+/// verify_password_with_ad() that rejects stored garlics above @max_g_high instead of
+/// DEFAULT_MAX_VERIFY_GARLIC, for services whose hashes legitimately use more memory or
+/// that want a tighter bound.
+pub fn verify_password_with_limits(
+    password: &[u8],
+    associated_data: &[u8],
+    encoded: &str,
+    max_g_high: u8) -> Result<bool, Error>
+{
+    verify_password_monitored(password, associated_data, encoded, max_g_high, &Monitor::default())
+}
+
+fn verify_password_monitored(
+    password: &[u8],
+    associated_data: &[u8],
+    encoded: &str,
+    max_g_high: u8,
+    monitor: &Monitor) -> Result<bool, Error>
+{
+    let (params, salt, hash) = decode_hash(encoded, max_g_high)?;
+    let mut catena = Catena::new_with_graph(params.g_high, params.graph)?;
+    catena.set_salt(&salt)?;
+    catena.set_associated_data(associated_data);
+    let mut output = vec![0u8; hash.len()];
+    catena.get_monitored(&mut output, password, params.g_low, params.lambda, params.use_phi, monitor)?;
+    let is_equal = ct_eq(&output, &hash);
+    rssc::op::secure_zero(&mut output);
+    Ok(is_equal)
+}
+
+/// hash_password() on a dedicated thread. Dropping the future cancels the hash and wipes its
+/// memory.
+#[cfg(feature = "async")]
+pub fn hash_password_async(password: &[u8], params: &Params) -> TaskFuture<String> {
+    let mut password = password.to_vec();
+    let params = *params;
+    task::spawn(move |cancel| {
+        let monitor = Monitor { cancel: Some(cancel), progress: None };
+        let result = hash_password_monitored(&password, &[], &params, &monitor);
+        rssc::op::secure_zero(&mut password);
+        result
+    })
+}
+
+/// verify_password() on a dedicated thread. Dropping the future cancels the verification and
+/// wipes its memory.
+#[cfg(feature = "async")]
+pub fn verify_password_async(password: &[u8], encoded: &str) -> TaskFuture<bool> {
+    let mut password = password.to_vec();
+    let encoded = encoded.to_string();
+    task::spawn(move |cancel| {
+        let monitor = Monitor { cancel: Some(cancel), progress: None };
+        let result = verify_password_monitored(&password, &[], &encoded, DEFAULT_MAX_VERIFY_GARLIC, &monitor);
+        rssc::op::secure_zero(&mut password);
+        result
+    })
+}
+
+/// Rewrite the encoded hash @encoded so that it verifies under @new_g_high instead of its
+/// current g_high, without the password. Lets a stored database be strengthened offline.
+pub fn upgrade_password_hash(encoded: &str, new_g_high: u8) -> Result<String, Error> {
+    // Only @new_g_high, which the caller chose, is allocated.
+    let (mut params, salt, hash) = decode_hash(encoded, u8::MAX)?;
+    if new_g_high <= params.g_high {
+        return Err(Error::BadParameter("new_g_high must be greater than the current g_high"));
+    }
+    let mut catena = Catena::new_with_graph(new_g_high, params.graph)?;
+    catena.set_salt(&salt)?;
+    let mut output = [0u8; NUM_HASH_BYTES];
+    catena.upgrade(&mut output, &hash, params.g_high, new_g_high, params.lambda, params.use_phi)?;
+    params.g_high = new_g_high;
+    Ok(encode_hash(&params, &salt, &output))
+}
+
+fn encode_hash(params: &Params, salt: &[u8], hash: &[u8]) -> String {
+    format!(
+        "${}$g={},gl={},l={},phi={}${}${}",
+        params.graph.phc_id(),
+        params.g_high,
+        params.g_low,
+        params.lambda,
+        params.use_phi as u8,
+        base64::encode(salt),
+        base64::encode(hash)
+    )
+}
+
+/// Parse @encoded, rejecting a g_high above @max_g_high.
+fn decode_hash(encoded: &str, max_g_high: u8) -> Result<(Params, Vec<u8>, Vec<u8>), Error> {
+    const MALFORMED: Error = Error::BadParameter("malformed Catena hash string");
+    let fields: Vec<&str> = encoded.split('$').collect();
+    if fields.len() != 5 || !fields[0].is_empty() {
+        return Err(MALFORMED);
+    }
+    let graph = match fields[1] {
+        PHC_ID_DRAGONFLY => Graph::BitReversal,
+        PHC_ID_BUTTERFLY => Graph::DoubleButterfly,
+        _ => return Err(MALFORMED)
+    };
+    let small = |x: u64| u8::try_from(x).map_err(|_| MALFORMED);
+    let [g_high, g_low, lambda, phi] = phc::parse_params(fields[2], &["g", "gl", "l", "phi"])
+        .ok_or(MALFORMED)?;
+    let (g_high, g_low, lambda) = (small(g_high)?, small(g_low)?, small(lambda)?);
+    if phi > 1 {
+        return Err(MALFORMED);
+    }
+    let params = Params { g_low, g_high, lambda, use_phi: phi == 1, graph };
+    params.validate()?;
+    if g_high > max_g_high {
+        return Err(Error::BadParameter("stored g_high exceeds the verification limit"));
+    }
+
+    let salt = match base64::decode(fields[3]) {
+        Some(s) if s.len() >= MIN_SALT_BYTES && s.len() <= MAX_SALT_BYTES => s,
+        _ => return Err(MALFORMED)
+    };
+    let hash = match base64::decode(fields[4]) {
+        Some(h) if (MIN_VERIFY_HASH_BYTES..=MAX_OUTPUT_BYTES).contains(&h.len()) => h,
+        _ => return Err(MALFORMED)
+    };
+    Ok((params, salt, hash))
+}
+
+/// The number of leading zero bits of @bytes, most significant bit of the first byte first.
+fn leading_zero_bits(bytes: &[u8]) -> u32 {
+    let mut count = 0u32;
+    for &b in bytes {
+        count += b.leading_zeros();
+        if b != 0 {
+            break;
+        }
+    }
+    count
+}
+
+/// Constant-time byte comparison.
+fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() { return false; }
+    let mut diff = 0u8;
+    for i in 0usize..a.len() {
+        diff |= a[i] ^ b[i];
+    }
+    diff == 0u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_PARAMS: Params = Params {
+        g_low: 8, g_high: 8, lambda: 1, use_phi: false, graph: Graph::BitReversal
+    };
+
+    fn is_zeroed<T>(value: &T) -> bool {
+        let bytes = unsafe {std::slice::from_raw_parts(value as *const T as *const u8, std::mem::size_of::<T>())};
+        bytes.iter().all(|&b| b == 0)
+    }
+
+    impl Catena {
+        /// Whether everything but the allocation itself is back in its freshly allocated state.
+        pub(crate) fn is_wiped(&self) -> bool {
+            let gm = unsafe {std::slice::from_raw_parts(self.graph_memory, self.get_num_allocated_bytes())};
+            gm.iter().all(|&b| b == 0)
+                && is_zeroed(&self.skein512)
+                && is_zeroed(&self.x)
+                && is_zeroed(&self.temp)
+                && is_zeroed(&self.salt)
+                && self.salt_len == NUM_SALT_BYTES
+                && self.ad_hash.is_none()
+        }
+    }
+
+    #[test]
+    fn hash_and_verify_password() {
+        let encoded = hash_password(b"password", &TEST_PARAMS).unwrap();
+        assert!(encoded.starts_with("$catena-dragonfly-skein512$g=8,gl=8,l=1,phi=0$"));
+        assert!(verify_password(b"password", &encoded).unwrap());
+        assert!(!verify_password(b"Password", &encoded).unwrap());
+        // Fresh salt each time.
+        assert_ne!(encoded, hash_password(b"password", &TEST_PARAMS).unwrap());
+    }
+
+    #[test]
+    fn upgrade_matches_get() {
+        let salt = [0xA5u8; NUM_SALT_BYTES];
+        for use_phi in [false, true] {
+            let mut low = Catena::new(7).unwrap();
+            low.set_salt(&salt).unwrap();
+            let mut stored = [0u8; NUM_HASH_BYTES];
+            low.get(&mut stored, b"password", 6, 2, use_phi).unwrap();
+
+            let mut high = Catena::new(9).unwrap();
+            high.set_salt(&salt).unwrap();
+            let mut expected = [0u8; NUM_HASH_BYTES];
+            high.get(&mut expected, b"password", 6, 2, use_phi).unwrap();
+
+            let mut upgraded = [0u8; NUM_HASH_BYTES];
+            high.upgrade(&mut upgraded, &stored, 7, 9, 2, use_phi).unwrap();
+            assert_eq!(upgraded, expected);
+
+            assert_eq!(
+                high.upgrade(&mut upgraded, &stored, 0, 9, 2, use_phi),
+                Err(Error::BadParameter("old_g_high must be at least 1"))
+            );
+            assert_eq!(
+                high.upgrade(&mut upgraded, &stored, 9, 8, 2, use_phi),
+                Err(Error::BadParameter("new_g_high must not be less than old_g_high"))
+            );
+        }
+    }
+
+    #[test]
+    fn server_relief_matches_get() {
+        for (g_low, g_high, lambda, use_phi) in [(5u8, 5u8, 1u8, false), (4, 7, 2, true), (8, 8, 3, true)] {
+            let mut catena = Catena::new(g_high).unwrap();
+            catena.set_salt(&[0x3Cu8; NUM_SALT_BYTES]).unwrap();
+            let mut expected = [0u8; NUM_HASH_BYTES];
+            catena.get(&mut expected, b"password", g_low, lambda, use_phi).unwrap();
+
+            let mut pre_final = [0u8; NUM_HASH_BYTES];
+            catena.client_relief(&mut pre_final, b"password", g_low, lambda, use_phi).unwrap();
+            assert_ne!(pre_final, expected);
+
+            let mut finished = [0u8; NUM_HASH_BYTES];
+            Catena::server_finish(&mut finished, &pre_final, g_high).unwrap();
+            assert_eq!(finished, expected);
+        }
+    }
+
+    #[test]
+    fn keyed_and_rekey() {
+        let mut catena = Catena::new(6).unwrap();
+        catena.set_salt(&[0x11u8; NUM_SALT_BYTES]).unwrap();
+        let mut plain = [0u8; NUM_HASH_BYTES];
+        catena.get(&mut plain, b"password", 6, 1, false).unwrap();
+
+        let mut keyed = [0u8; NUM_HASH_BYTES];
+        let old_key = ServerKey { key: b"old key", user_id: 42 };
+        catena.get_keyed(&mut keyed, b"password", &old_key, 6, 1, false).unwrap();
+        assert_ne!(keyed, plain);
+        let mut other_user = [0u8; NUM_HASH_BYTES];
+        catena.get_keyed(&mut other_user, b"password", &ServerKey { user_id: 43, ..old_key }, 6, 1, false).unwrap();
+        assert_ne!(keyed, other_user);
+
+        let mut expected = [0u8; NUM_HASH_BYTES];
+        catena.get_keyed(&mut expected, b"password", &ServerKey { key: b"new key", ..old_key }, 6, 1, false).unwrap();
+        Catena::rekey(&mut keyed, b"old key", b"new key", 42, 6).unwrap();
+        assert_eq!(keyed, expected);
+
+        Catena::apply_key(&mut keyed, b"new key", 42, 6).unwrap();
+        assert_eq!(keyed, plain);
+    }
+
+    #[test]
+    fn upgrade_password_hash_verifies() {
+        let encoded = hash_password(b"password", &TEST_PARAMS).unwrap();
+        let upgraded = upgrade_password_hash(&encoded, 10).unwrap();
+        assert!(upgraded.starts_with("$catena-dragonfly-skein512$g=10,gl=8,"));
+        assert!(verify_password(b"password", &upgraded).unwrap());
+        assert!(!verify_password(b"password", &encoded.replacen("g=8,", "g=10,", 1)).unwrap());
+        assert!(upgrade_password_hash(&upgraded, 10).is_err());
+    }
+
+    #[test]
+    fn butterfly_differs_from_dragonfly() {
+        for use_phi in [false, true] {
+            let mut dragonfly = Catena::new(6).unwrap();
+            let mut butterfly = Catena::new_with_graph(6, Graph::DoubleButterfly).unwrap();
+            assert_eq!(butterfly.get_num_allocated_bytes(), dragonfly.get_num_allocated_bytes() * 2);
+            let mut d_out = [0u8; NUM_HASH_BYTES];
+            let mut b_out = [0u8; NUM_HASH_BYTES];
+            dragonfly.get(&mut d_out, b"password", 4, 2, use_phi).unwrap();
+            butterfly.get(&mut b_out, b"password", 4, 2, use_phi).unwrap();
+            assert_ne!(d_out, b_out);
+            // Deterministic.
+            let mut b_again = [0u8; NUM_HASH_BYTES];
+            butterfly.get(&mut b_again, b"password", 4, 2, use_phi).unwrap();
+            assert_eq!(b_out, b_again);
+        }
+        // Switching the graph of an instance reallocates its memory to match.
+        let mut switched = Catena::new(6).unwrap();
+        switched.set_graph(Graph::DoubleButterfly).unwrap();
+        assert_eq!(switched.get_graph(), Graph::DoubleButterfly);
+        assert_eq!(switched.get_num_allocated_bytes(), Graph::DoubleButterfly.num_graph_bytes(6).unwrap());
+        let mut expected = [0u8; NUM_HASH_BYTES];
+        let mut output   = [0u8; NUM_HASH_BYTES];
+        Catena::new_with_graph(6, Graph::DoubleButterfly).unwrap().get(&mut expected, b"password", 4, 2, true).unwrap();
+        switched.get(&mut output, b"password", 4, 2, true).unwrap();
+        assert_eq!(output, expected);
+
+        let params = Params { graph: Graph::DoubleButterfly, ..TEST_PARAMS };
+        let encoded = hash_password(b"password", &params).unwrap();
+        assert!(encoded.starts_with("$catena-butterfly-skein512$"));
+        assert!(verify_password(b"password", &encoded).unwrap());
+        assert!(!verify_password(b"password", &encoded.replacen("butterfly", "dragonfly", 1)).unwrap());
+    }
+
+    #[test]
+    fn long_passwords() {
+        let password = [0x61u8; 1000];
+        let mut catena = Catena::new(5).unwrap();
+        let mut long  = [0u8; NUM_HASH_BYTES];
+        let mut short = [0u8; NUM_HASH_BYTES];
+        catena.get(&mut long, &password, 5, 1, false).unwrap();
+        catena.get(&mut short, &password[..999], 5, 1, false).unwrap();
+        assert_ne!(long, short);
+    }
+
+    #[test]
+    fn salt_and_output_lengths() {
+        let mut catena = Catena::new(5).unwrap();
+        assert!(catena.set_salt(&[0u8; MIN_SALT_BYTES - 1]).is_err());
+        assert!(catena.set_salt(&[0u8; MAX_SALT_BYTES + 1]).is_err());
+
+        let mut outputs = Vec::new();
+        for salt_len in [MIN_SALT_BYTES, NUM_SALT_BYTES, MAX_SALT_BYTES] {
+            catena.set_salt(&[0x77u8; MAX_SALT_BYTES][..salt_len]).unwrap();
+            let mut out = [0u8; NUM_HASH_BYTES];
+            catena.get(&mut out, b"password", 5, 1, false).unwrap();
+            outputs.push(out.to_vec());
+        }
+        assert_ne!(outputs[0], outputs[1]);
+        assert_ne!(outputs[1], outputs[2]);
+
+        let mut key    = [0u8; 32];
+        let mut key_iv = [0u8; 96];
+        catena.get(&mut key, b"password", 5, 1, false).unwrap();
+        catena.get(&mut key_iv, b"password", 5, 1, false).unwrap();
+        assert_ne!(key[..], key_iv[..32]);
+        assert_ne!(key_iv[..64], outputs[2][..]);
+        assert!(catena.get(&mut [], b"password", 5, 1, false).is_err());
+    }
+
+    #[test]
+    fn verify_accepts_other_lengths() {
+        let params = TEST_PARAMS;
+        let mut catena = Catena::new(params.g_high).unwrap();
+        catena.set_salt(&[0x42u8; MIN_SALT_BYTES]).unwrap();
+        let mut output = [0u8; 32];
+        catena.get(&mut output, b"password", params.g_low, params.lambda, params.use_phi).unwrap();
+        let encoded = encode_hash(&params, catena.get_salt(), &output);
+        assert!(verify_password(b"password", &encoded).unwrap());
+        assert!(!verify_password(b"passwore", &encoded).unwrap());
+    }
+
+    #[test]
+    fn proof_of_work() {
+        let params = Params { g_low: 4, g_high: 4, lambda: 1, use_phi: false, graph: Graph::BitReversal };
+        let nonce = Catena::pow_solve(b"challenge", 5, &params).unwrap();
+        assert!(Catena::pow_verify(b"challenge", nonce, 5, &params).unwrap());
+        // pow_solve() returns the smallest solution.
+        for smaller in 0..nonce {
+            assert!(!Catena::pow_verify(b"challenge", smaller, 5, &params).unwrap());
+        }
+        assert_eq!(
+            Catena::pow_verify(b"challenge", nonce, MAX_POW_DIFFICULTY + 1, &params),
+            Err(Error::BadParameter("difficulty_bits must not exceed MAX_POW_DIFFICULTY"))
+        );
+        assert!(Catena::pow_solve(b"challenge", MAX_POW_DIFFICULTY + 1, &params).is_err());
+        assert!(Catena::pow_verify(b"challenge", nonce, MAX_POW_DIFFICULTY, &params).is_ok());
+        let cancel = AtomicBool::new(true);
+        let monitor = Monitor { cancel: Some(&cancel), progress: None };
+        assert_eq!(
+            Catena::pow_solve_monitored(b"challenge", MAX_POW_DIFFICULTY, &params, &monitor),
+            Err(Error::Cancelled)
+        );
+
+        // The POW domain separates puzzle outputs from password hashes over the same inputs.
+        let mut catena = Catena::new_pow(b"challenge", 5, &params).unwrap();
+        let mut pow = [0u8; NUM_HASH_BYTES];
+        let mut kdf = [0u8; NUM_HASH_BYTES];
+        catena.get_in_domain(&mut pow, &nonce.to_le_bytes(), params.cost(), DOMAIN_POW, &Monitor::default()).unwrap();
+        catena.get(&mut kdf, &nonce.to_le_bytes(), 4, 1, false).unwrap();
+        assert_ne!(pow, kdf);
+        assert_eq!(leading_zero_bits(&[0, 0x10, 0xFF]), 11);
+    }
+
+    #[test]
+    fn calibrate_respects_memory() {
+        let max_memory_bytes = 1usize << 16;
+        let params = calibrate(std::time::Duration::from_millis(50), max_memory_bytes, false).unwrap();
+        params.validate().unwrap();
+        assert!(params.get_num_allocated_bytes() <= max_memory_bytes);
+        let catena = Catena::new_with_graph(params.g_high, params.graph).unwrap();
+        assert_eq!(catena.get_num_allocated_bytes(), params.get_num_allocated_bytes());
+        assert!(calibrate(std::time::Duration::from_millis(50), 64, false).is_err());
+    }
+
+    #[test]
+    fn memory_policies_agree() {
+        let mut locked   = Catena::new_with_memory_policy(6, Graph::BitReversal, MemoryPolicy::Locked).unwrap();
+        let mut unlocked = Catena::new_with_memory_policy(6, Graph::BitReversal, MemoryPolicy::Unlocked).unwrap();
+        assert!(!unlocked.is_memory_locked());
+        assert_eq!(locked.get_num_allocated_bytes(), unlocked.get_num_allocated_bytes());
+        let mut l_out = [0u8; NUM_HASH_BYTES];
+        let mut u_out = [0u8; NUM_HASH_BYTES];
+        locked.get(&mut l_out, b"password", 6, 1, true).unwrap();
+        unlocked.get(&mut u_out, b"password", 6, 1, true).unwrap();
+        assert_eq!(l_out, u_out);
+    }
+
+    #[test]
+    fn reconfigure_matches_fresh_instance() {
+        let mut catena = Catena::new(5).unwrap();
+        catena.set_salt(&[0x99u8; 20]).unwrap();
+        for g_high in [8u8, 3, 3, 6] {
+            catena.reconfigure(g_high).unwrap();
+            assert_eq!(catena.g_high, g_high);
+            assert_eq!(catena.get_salt(), &[0x99u8; 20]);
+            let mut fresh = Catena::new(g_high).unwrap();
+            fresh.set_salt(&[0x99u8; 20]).unwrap();
+            let mut a = [0u8; NUM_HASH_BYTES];
+            let mut b = [0u8; NUM_HASH_BYTES];
+            catena.get(&mut a, b"password", 2, 1, false).unwrap();
+            fresh.get(&mut b, b"password", 2, 1, false).unwrap();
+            assert_eq!(a, b);
+        }
+        let mut uninit = Catena::default();
+        uninit.reconfigure(4).unwrap();
+        assert!(uninit.is_initialized());
+    }
+
+    #[test]
+    fn pool_shares_instances_between_threads() {
+        let pool = CatenaPool::new(2, 6, Graph::BitReversal).unwrap();
+        let mut expected = [0u8; NUM_HASH_BYTES];
+        pool.acquire().get(&mut expected, b"password", 6, 1, false).unwrap();
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut catena = pool.acquire();
+                    let mut out = [0u8; NUM_HASH_BYTES];
+                    catena.get(&mut out, b"password", 6, 1, false).unwrap();
+                    assert_eq!(out, expected);
+                });
+            }
+        });
+        assert_eq!(pool.num_available(), 2);
+        let a = pool.try_acquire().unwrap();
+        let _b = pool.try_acquire().unwrap();
+        assert!(pool.try_acquire().is_none());
+        drop(a);
+        assert!(pool.try_acquire().is_some());
+    }
+
+    #[test]
+    fn pool_returns_wiped_instances() {
+        let pool = CatenaPool::new(1, 6, Graph::BitReversal).unwrap();
+        let mut output = [0u8; NUM_HASH_BYTES];
+        {
+            let mut catena = pool.acquire();
+            catena.set_salt(&[0xC3u8; MAX_SALT_BYTES]).unwrap();
+            catena.set_associated_data(b"alice@example.com");
+            catena.get(&mut output, b"password", 6, 1, true).unwrap();
+            assert!(!catena.is_wiped());
+        }
+        let catena = pool.acquire();
+        assert_eq!(catena.get_num_allocated_bytes(), Graph::BitReversal.num_graph_bytes(6).unwrap());
+        assert!(catena.is_wiped());
+    }
+
+    #[test]
+    fn monitor_reports_progress_and_cancels() {
+        let mut catena = Catena::new(7).unwrap();
+        let mut expected = [0u8; NUM_HASH_BYTES];
+        catena.get(&mut expected, b"password", 4, 2, true).unwrap();
+
+        let reported = Mutex::new(Vec::new());
+        let record = |percent: u8| reported.lock().unwrap().push(percent);
+        let monitor = Monitor { cancel: None, progress: Some(&record) };
+        let mut output = [0u8; NUM_HASH_BYTES];
+        catena.get_monitored(&mut output, b"password", 4, 2, true, &monitor).unwrap();
+        assert_eq!(output, expected);
+        let reported = reported.into_inner().unwrap();
+        assert!(reported.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(reported.last(), Some(&100));
+
+        // Cancel once the computation is a third of the way through.
+        let cancel = AtomicBool::new(false);
+        let cancel_early = |percent: u8| if percent >= 33 { cancel.store(true, Ordering::Relaxed) };
+        let monitor = Monitor { cancel: Some(&cancel), progress: Some(&cancel_early) };
+        assert_eq!(
+            catena.get_monitored(&mut output, b"password", 4, 2, true, &monitor),
+            Err(Error::Cancelled)
+        );
+        let gm = unsafe {std::slice::from_raw_parts(catena.graph_memory, catena.get_num_allocated_bytes())};
+        assert!(gm.iter().all(|&b| b == 0));
+        assert!(is_zeroed(&catena.x));
+        assert!(is_zeroed(&catena.temp));
+        assert!(is_zeroed(&catena.skein512));
+
+        // The instance remains usable after cancellation.
+        catena.get(&mut output, b"password", 4, 2, true).unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn associated_data_separates_outputs() {
+        let mut catena = Catena::new(6).unwrap();
+        let mut plain = [0u8; NUM_HASH_BYTES];
+        let mut empty = [0u8; NUM_HASH_BYTES];
+        let mut alice = [0u8; NUM_HASH_BYTES];
+        let mut bob   = [0u8; NUM_HASH_BYTES];
+        catena.get(&mut plain, b"password", 6, 1, false).unwrap();
+        catena.get_with_ad(&mut empty, b"password", b"", 6, 1, false).unwrap();
+        catena.get_with_ad(&mut alice, b"password", b"alice@example.com", 6, 1, false).unwrap();
+        catena.get_with_ad(&mut bob,   b"password", b"bob@example.com",   6, 1, false).unwrap();
+        assert_eq!(plain, empty);
+        assert_ne!(plain, alice);
+        assert_ne!(alice, bob);
+        // get_with_ad() leaves the instance's own associated data alone.
+        let mut again = [0u8; NUM_HASH_BYTES];
+        catena.get(&mut again, b"password", 6, 1, false).unwrap();
+        assert_eq!(again, plain);
+        catena.set_associated_data(b"alice@example.com");
+        catena.get(&mut again, b"password", 6, 1, false).unwrap();
+        assert_eq!(again, alice);
+
+        let encoded = hash_password_with_ad(b"password", b"service-a", &TEST_PARAMS).unwrap();
+        assert!(verify_password_with_ad(b"password", b"service-a", &encoded).unwrap());
+        assert!(!verify_password_with_ad(b"password", b"service-b", &encoded).unwrap());
+        assert!(!verify_password(b"password", &encoded).unwrap());
+
+        // Without the flag, associated data would be indistinguishable from a password that
+        // begins with its hash.
+        let mut prefixed = catena.ad_hash.unwrap().to_vec();
+        prefixed.extend_from_slice(b"password");
+        catena.set_associated_data(b"");
+        let mut unbound = [0u8; NUM_HASH_BYTES];
+        catena.get(&mut unbound, &prefixed, 6, 1, false).unwrap();
+        assert_ne!(unbound, alice);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_hash_and_verify() {
+        use crate::task::block_on;
+        let encoded = block_on(hash_password_async(b"password", &TEST_PARAMS)).unwrap();
+        assert!(verify_password(b"password", &encoded).unwrap());
+        assert!(block_on(verify_password_async(b"password", &encoded)).unwrap());
+        assert!(!block_on(verify_password_async(b"wrong", &encoded)).unwrap());
+    }
+
+    #[test]
+    fn verify_rejects_malformed() {
+        let encoded = hash_password(b"password", &TEST_PARAMS).unwrap();
+        let bad = [
+            encoded.replacen("dragonfly", "moth", 1),
+            encoded.replacen("g=8,", "g=08,", 1),
+            encoded.replacen("phi=0", "phi=2", 1),
+            encoded.replacen(",l=1", "", 1),
+            encoded.replacen("gl=8", "gl=9", 1),
+            // No hash field at all.
+            encoded[..encoded.rfind('$').unwrap()].to_string(),
+            // A hash of 85 Base64 characters, a length no byte string encodes to. Shorter cuts
+            // may still decode to a valid, shorter hash and merely fail to match.
+            encoded[..encoded.len() - 1].to_string(),
+        ];
+        for b in &bad {
+            assert!(matches!(verify_password(b"password", b), Err(Error::BadParameter(_))), "{}", b);
+        }
+        // Oversized garlics are refused before allocating anything.
+        let huge = encoded.replacen("g=8,", "g=30,", 1);
+        assert_eq!(
+            verify_password(b"password", &huge),
+            Err(Error::BadParameter("stored g_high exceeds the verification limit"))
+        );
+        assert_eq!(
+            verify_password_with_limits(b"password", &[], &encoded, 7),
+            Err(Error::BadParameter("stored g_high exceeds the verification limit"))
+        );
+        assert!(verify_password_with_limits(b"password", &[], &encoded, 8).unwrap());
+        let truncated = &encoded[..encoded.len() - 4];
+        assert!(!matches!(verify_password(b"password", truncated), Ok(true)), "{}", truncated);
+        // Stored hashes shorter than MIN_VERIFY_HASH_BYTES are refused outright.
+        let (params, salt, hash) = decode_hash(&encoded, u8::MAX).unwrap();
+        let short = encode_hash(&params, &salt, &hash[..MIN_VERIFY_HASH_BYTES - 1]);
+        assert!(matches!(verify_password(b"password", &short), Err(Error::BadParameter(_))), "{}", short);
+        let one = encode_hash(&params, &salt, &hash[..1]);
+        assert!(matches!(verify_password(b"password", &one), Err(Error::BadParameter(_))), "{}", one);
+        let shortest = encode_hash(&params, &salt, &hash[..MIN_VERIFY_HASH_BYTES]);
+        assert!(!verify_password(b"password", &shortest).unwrap());
+    }
+}
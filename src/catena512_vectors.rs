@@ -0,0 +1,393 @@
+/* *
+ * tsc - Implement Threefish, Skein, and CATENA cryptographic algorithms.
+ * Copyright (C) 2025 Stuart Calder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Regression vectors for Catena-Dragonfly-Skein512, Catena-Butterfly-Skein512 and the
+//! multi-threaded KDF.
+//!
+//! These vectors were generated by this crate's original Catena implementation, before any
+//! changes to the password input, salt or output handling, and pin its behaviour exactly.
+//! They are NOT reference vectors: they have not been checked against the C tsc/3crypt
+//! implementation the version IDs were taken from. Vectors produced by that implementation,
+//! with the same salts and passwords and covering g_low < g_high and phi, still need to be
+//! added to VECTORS so the two are cross-checked.
+//! BUTTERFLY_VECTORS were generated by this crate when Catena-Butterfly was added, and pin
+//! its graph and version IDs against regressions in the same way.
+//! The remaining tests cross-check the alternative code paths (server relief, garlic
+//! upgrade, reused and pooled instances) against the same vectors.
+
+use crate::catena512::*;
+use crate::kdf;
+use crate::skein512::Skein512;
+
+const ZERO_SALT:     [u8; NUM_SALT_BYTES] = [0u8; NUM_SALT_BYTES];
+const COUNTING_SALT: [u8; NUM_SALT_BYTES] = {
+    let mut salt = [0u8; NUM_SALT_BYTES];
+    let mut i = 0;
+    while i < NUM_SALT_BYTES {
+        salt[i] = i as u8;
+        i += 1;
+    }
+    salt
+};
+
+struct Vector {
+    g_low:    u8,
+    g_high:   u8,
+    lambda:   u8,
+    use_phi:  bool,
+    password: &'static [u8],
+    salt:     &'static [u8; NUM_SALT_BYTES],
+    output:   [u8; NUM_HASH_BYTES],
+}
+
+const VECTORS: &[Vector] = &[
+    Vector {
+        g_low: 13, g_high: 13, lambda: 1, use_phi: false,
+        password: b"password",
+        salt: &ZERO_SALT,
+        output: [
+            0x40, 0x43, 0x19, 0x83, 0x53, 0x62, 0x54, 0xee,
+            0x44, 0xcd, 0xd1, 0xe0, 0xd6, 0xf2, 0x0e, 0xc5,
+            0x3a, 0xf8, 0xea, 0x10, 0xb6, 0xa2, 0xf5, 0x4f,
+            0x14, 0x95, 0xed, 0xae, 0xde, 0xd6, 0xce, 0x58,
+            0x2c, 0x43, 0x19, 0xca, 0xfc, 0xc3, 0xcb, 0x6d,
+            0x96, 0x29, 0xd6, 0xa2, 0xf1, 0xc1, 0x20, 0x9c,
+            0xc3, 0x7d, 0x80, 0x5b, 0xfb, 0xdf, 0x21, 0x26,
+            0xd6, 0xbb, 0x93, 0xf5, 0xa9, 0x81, 0xd3, 0x68
+        ],
+    },
+    Vector {
+        g_low: 8, g_high: 8, lambda: 1, use_phi: false,
+        password: b"password",
+        salt: &COUNTING_SALT,
+        output: [
+            0x6d, 0x42, 0xc0, 0x3e, 0x11, 0x27, 0x0b, 0x2e,
+            0xae, 0x73, 0xae, 0x90, 0x2b, 0xf1, 0x5f, 0x8d,
+            0xc3, 0xf1, 0xd2, 0x40, 0x97, 0xd7, 0x75, 0x21,
+            0xa3, 0x9c, 0xe8, 0x47, 0x8d, 0x92, 0x92, 0x7a,
+            0x0b, 0xe7, 0x53, 0xdf, 0xd4, 0x74, 0x41, 0x2a,
+            0xa1, 0x96, 0xbd, 0x66, 0xaf, 0x15, 0x59, 0x11,
+            0x9c, 0xf4, 0x40, 0x7b, 0x1b, 0x52, 0x17, 0x7c,
+            0x1c, 0x31, 0xca, 0x7f, 0xb1, 0x71, 0xbb, 0x69
+        ],
+    },
+    Vector {
+        g_low: 8, g_high: 8, lambda: 1, use_phi: true,
+        password: b"password",
+        salt: &COUNTING_SALT,
+        output: [
+            0x01, 0x59, 0xe6, 0xcf, 0x36, 0xb8, 0x29, 0x4e,
+            0xbb, 0x47, 0xc1, 0x9e, 0x68, 0xb2, 0xbf, 0xc2,
+            0xb8, 0xb8, 0x7f, 0x95, 0x34, 0x66, 0xf6, 0xe3,
+            0x51, 0xe9, 0x69, 0xf8, 0xef, 0x39, 0xe3, 0xbc,
+            0xd9, 0xd8, 0x54, 0x4b, 0xd2, 0x96, 0x60, 0xec,
+            0x0d, 0x21, 0xd6, 0xdf, 0x04, 0x98, 0xf5, 0xec,
+            0x5a, 0xe4, 0x96, 0xfa, 0xbf, 0xc3, 0xbe, 0x27,
+            0x86, 0x27, 0x2e, 0x18, 0x44, 0xe9, 0xc9, 0x0c
+        ],
+    },
+    Vector {
+        g_low: 6, g_high: 10, lambda: 2, use_phi: false,
+        password: b"password",
+        salt: &COUNTING_SALT,
+        output: [
+            0x6f, 0xb8, 0x97, 0x64, 0x8c, 0xc7, 0xa3, 0x83,
+            0xdf, 0x4f, 0x5b, 0xda, 0xf3, 0x12, 0xda, 0xef,
+            0xe2, 0xfc, 0xe3, 0xfc, 0xda, 0xb0, 0xf9, 0xbf,
+            0xaa, 0xad, 0xed, 0xa0, 0xef, 0xde, 0xaf, 0xbb,
+            0xbb, 0xb1, 0x05, 0x69, 0xf9, 0x1f, 0x6d, 0xb1,
+            0x2a, 0xee, 0x41, 0x1a, 0xa0, 0xa7, 0x2b, 0x56,
+            0x95, 0xde, 0x4c, 0xc3, 0x97, 0x3e, 0x9a, 0xb6,
+            0x2b, 0xdd, 0xad, 0x61, 0xa4, 0x55, 0x32, 0x5b
+        ],
+    },
+    Vector {
+        g_low: 6, g_high: 10, lambda: 2, use_phi: true,
+        password: b"password",
+        salt: &COUNTING_SALT,
+        output: [
+            0xa2, 0x4f, 0xd3, 0xcd, 0xef, 0x5c, 0x6e, 0x33,
+            0x85, 0x88, 0xfb, 0x56, 0xd8, 0x24, 0xe6, 0xcc,
+            0xb3, 0x7c, 0x9e, 0xc8, 0xbb, 0x37, 0x12, 0xe4,
+            0xea, 0xda, 0x1a, 0x77, 0xb3, 0xc6, 0x84, 0xb3,
+            0x5b, 0x1e, 0x37, 0x91, 0xfd, 0x85, 0xfc, 0x79,
+            0x78, 0x89, 0xca, 0x52, 0xb1, 0xa0, 0xba, 0x35,
+            0x80, 0x67, 0x23, 0x0e, 0xa0, 0x92, 0x97, 0x64,
+            0x68, 0x62, 0x4d, 0x07, 0xa7, 0x71, 0x1c, 0x23
+        ],
+    },
+    Vector {
+        g_low: 1, g_high: 5, lambda: 4, use_phi: true,
+        password: b"",
+        salt: &COUNTING_SALT,
+        output: [
+            0x8e, 0x42, 0x14, 0xf9, 0xd7, 0x4f, 0xbb, 0x06,
+            0x6e, 0x45, 0x8f, 0x7d, 0x21, 0xc8, 0x6b, 0x62,
+            0xa9, 0x87, 0x83, 0x73, 0x26, 0x96, 0x34, 0x6a,
+            0xea, 0x38, 0xa1, 0x4d, 0xe0, 0xe5, 0xae, 0xcf,
+            0x49, 0x5d, 0x67, 0xf0, 0x45, 0x59, 0x54, 0xdc,
+            0x56, 0x4f, 0x5f, 0x90, 0x22, 0xae, 0x4b, 0x91,
+            0x2d, 0xf2, 0x29, 0x09, 0x06, 0xd0, 0x74, 0x71,
+            0x07, 0x00, 0xce, 0x87, 0x74, 0x79, 0x51, 0xb4
+        ],
+    },
+    Vector {
+        g_low: 12, g_high: 12, lambda: 3, use_phi: true,
+        password: b"correct horse battery staple",
+        salt: &COUNTING_SALT,
+        output: [
+            0x94, 0x7a, 0x35, 0x0c, 0x5d, 0x28, 0x6c, 0xdb,
+            0xcb, 0x28, 0x3d, 0xa2, 0xca, 0xff, 0x91, 0x37,
+            0x30, 0xce, 0xb9, 0xeb, 0x03, 0xae, 0xec, 0x7f,
+            0x3e, 0x10, 0x55, 0x4e, 0xb7, 0x5f, 0x9b, 0x12,
+            0x3c, 0xfd, 0x2b, 0x8e, 0x58, 0x53, 0x6f, 0x0c,
+            0x06, 0x48, 0xf4, 0xa8, 0xa0, 0xb2, 0xe8, 0x61,
+            0x3f, 0x62, 0x6b, 0x04, 0x0f, 0x40, 0x86, 0xab,
+            0xa4, 0xe9, 0x16, 0x98, 0x64, 0xbe, 0x9c, 0x9c
+        ],
+    },
+    Vector {
+        g_low: 3, g_high: 11, lambda: 1, use_phi: false,
+        password: b"correct horse battery staple",
+        salt: &COUNTING_SALT,
+        output: [
+            0x63, 0xcf, 0x0c, 0x65, 0xe7, 0x81, 0xad, 0x2b,
+            0x63, 0x20, 0xe7, 0x49, 0x9d, 0x85, 0x36, 0xb7,
+            0x02, 0x9c, 0x4a, 0xf7, 0xf7, 0xdf, 0xdf, 0x3e,
+            0x3c, 0xfa, 0x77, 0xee, 0xba, 0xf7, 0xb7, 0x4a,
+            0x88, 0x7a, 0x7b, 0xef, 0x5c, 0x7b, 0x4c, 0x18,
+            0x3d, 0xde, 0xab, 0x7e, 0xb5, 0xb4, 0x6b, 0x9e,
+            0x46, 0x89, 0x76, 0x56, 0xd2, 0x8e, 0xbd, 0x30,
+            0xfc, 0x5e, 0xff, 0x6b, 0x8c, 0x13, 0xa1, 0x5f
+        ],
+    },
+];
+
+/// Catena-Butterfly (Graph::DoubleButterfly) under the same conventions as VECTORS.
+const BUTTERFLY_VECTORS: &[Vector] = &[
+    Vector {
+        g_low: 8, g_high: 8, lambda: 1, use_phi: false,
+        password: b"password",
+        salt: &COUNTING_SALT,
+        output: [
+            0x03, 0xf6, 0x07, 0xec, 0xcd, 0x53, 0x5b, 0x98,
+            0x72, 0xd8, 0x25, 0xff, 0x65, 0xcc, 0x86, 0x6f,
+            0x86, 0x85, 0x60, 0x91, 0x50, 0xcc, 0x40, 0xbd,
+            0x78, 0x64, 0x42, 0xd8, 0x30, 0x3b, 0xab, 0x3b,
+            0xcb, 0xb9, 0x56, 0x67, 0xfc, 0xaf, 0x9e, 0xcc,
+            0x2a, 0xb8, 0xfe, 0x44, 0x3b, 0x35, 0x89, 0xb4,
+            0x47, 0x45, 0x69, 0x0b, 0xff, 0xc3, 0x0d, 0x58,
+            0xde, 0x1d, 0x5d, 0x11, 0xaa, 0x4d, 0x52, 0x46
+        ],
+    },
+    Vector {
+        g_low: 8, g_high: 8, lambda: 1, use_phi: true,
+        password: b"password",
+        salt: &COUNTING_SALT,
+        output: [
+            0xab, 0xbf, 0xa2, 0xd6, 0x88, 0xb6, 0xba, 0x99,
+            0x36, 0xf6, 0xea, 0xad, 0x09, 0x04, 0xb0, 0xd3,
+            0xe0, 0xfd, 0x8b, 0xce, 0x81, 0xba, 0xd5, 0xa4,
+            0x63, 0xf9, 0x58, 0xf0, 0x52, 0x64, 0x34, 0x3d,
+            0x01, 0xd6, 0xe4, 0x1d, 0xaf, 0x02, 0xe8, 0x5e,
+            0x1a, 0xb9, 0xb8, 0xdd, 0xe9, 0x6f, 0xbc, 0xe2,
+            0x0d, 0xf6, 0x48, 0x9a, 0x7b, 0x71, 0x26, 0x8c,
+            0x46, 0x23, 0xe4, 0x40, 0xd0, 0x9e, 0xc7, 0xdd
+        ],
+    },
+    Vector {
+        g_low: 5, g_high: 9, lambda: 2, use_phi: false,
+        password: b"correct horse battery staple",
+        salt: &COUNTING_SALT,
+        output: [
+            0x91, 0x8e, 0x75, 0x8f, 0x54, 0xf8, 0x61, 0x5b,
+            0x5b, 0x7b, 0x94, 0xb1, 0x96, 0x1e, 0x58, 0xf9,
+            0x57, 0x4e, 0xba, 0x6b, 0xa2, 0xf4, 0x59, 0x16,
+            0x8e, 0x2d, 0xad, 0x4b, 0x85, 0xf9, 0x95, 0x69,
+            0x96, 0x56, 0x1f, 0x66, 0xa7, 0x33, 0x2c, 0xad,
+            0x88, 0xf3, 0x7b, 0xef, 0xef, 0x3f, 0x19, 0x55,
+            0xad, 0x0b, 0x1d, 0xaf, 0x7a, 0x64, 0x4f, 0xee,
+            0x8c, 0xce, 0xd0, 0x4f, 0x23, 0xd9, 0x9a, 0x5a
+        ],
+    },
+    Vector {
+        g_low: 5, g_high: 9, lambda: 2, use_phi: true,
+        password: b"correct horse battery staple",
+        salt: &COUNTING_SALT,
+        output: [
+            0xb3, 0x8e, 0x04, 0xf1, 0xa2, 0x00, 0x97, 0xa0,
+            0x01, 0x80, 0x94, 0xd8, 0x37, 0x29, 0x07, 0x19,
+            0xec, 0xea, 0xae, 0x07, 0x99, 0xbf, 0x94, 0xd6,
+            0x0e, 0x31, 0x94, 0xc2, 0xab, 0xc2, 0xa9, 0xa6,
+            0x41, 0xb7, 0x5f, 0x27, 0x22, 0x80, 0x49, 0x55,
+            0xe1, 0x62, 0xf1, 0x33, 0x88, 0x75, 0xbf, 0x21,
+            0x27, 0x2a, 0xac, 0xbd, 0xf5, 0x1f, 0x26, 0xf4,
+            0x59, 0x2f, 0xc0, 0x64, 0xf2, 0x64, 0x1d, 0xd4
+        ],
+    },
+];
+
+struct KdfVector {
+    thread_count:      u64,
+    thread_batch_size: u64,
+    memory_low:        u8,
+    memory_high:       u8,
+    iterations:        u8,
+    use_phi:           bool,
+    output:            [u8; kdf::NUM_OUTPUT_BYTES],
+}
+
+/// multi_threaded() with salt [9; 32] and password "hunter2".
+const KDF_VECTORS: &[KdfVector] = &[
+    KdfVector {
+        thread_count: 1, thread_batch_size: 1,
+        memory_low: 6, memory_high: 8, iterations: 1, use_phi: false,
+        output: [
+            0xdb, 0x5e, 0xee, 0x8e, 0x90, 0xda, 0x20, 0x09,
+            0xe0, 0x7f, 0xe0, 0x02, 0xbf, 0x07, 0xb0, 0x4a,
+            0x1e, 0xba, 0x4b, 0x3a, 0xe4, 0x11, 0x4a, 0x1e,
+            0x7c, 0xf1, 0xcb, 0x0d, 0x5c, 0x1d, 0xeb, 0xde,
+            0x3d, 0x87, 0xcc, 0xea, 0x27, 0xfa, 0x6b, 0x96,
+            0xa1, 0x09, 0xc3, 0xc5, 0x5d, 0x3c, 0x1a, 0x95,
+            0x39, 0xec, 0x05, 0xa1, 0x3d, 0x7e, 0x00, 0xe2,
+            0xc7, 0x0d, 0x8b, 0x0d, 0x47, 0x76, 0xfd, 0xdc
+        ],
+    },
+    KdfVector {
+        thread_count: 4, thread_batch_size: 2,
+        memory_low: 5, memory_high: 7, iterations: 2, use_phi: true,
+        output: [
+            0x31, 0xd0, 0x73, 0xa9, 0xf2, 0x3e, 0x59, 0x0e,
+            0x4f, 0x25, 0x20, 0xa9, 0x81, 0x33, 0x98, 0xcf,
+            0x5f, 0xbd, 0x75, 0x2d, 0xf8, 0x86, 0x5b, 0xef,
+            0x1f, 0x0c, 0xe0, 0x8e, 0x41, 0xd1, 0xce, 0x21,
+            0x73, 0xeb, 0x19, 0x51, 0xb4, 0x4b, 0x4b, 0xfa,
+            0x22, 0xd1, 0x1a, 0x6f, 0xa0, 0x3f, 0xe3, 0xcc,
+            0x54, 0x1b, 0xb9, 0xea, 0xd3, 0xe8, 0x40, 0x61,
+            0x2e, 0x0c, 0x6d, 0xbc, 0x87, 0x9b, 0x69, 0x24
+        ],
+    },
+];
+
+fn new_catena(v: &Vector, g_high: u8) -> Catena {
+    let mut catena = Catena::new(g_high).unwrap();
+    catena.set_salt(v.salt).unwrap();
+    catena
+}
+
+#[test]
+fn catena_known_answers() {
+    for v in VECTORS {
+        let mut catena = new_catena(v, v.g_high);
+        let mut output = [0u8; NUM_HASH_BYTES];
+        catena.get(&mut output, v.password, v.g_low, v.lambda, v.use_phi).unwrap();
+        assert_eq!(output, v.output, "g_low {} g_high {} lambda {} phi {}", v.g_low, v.g_high, v.lambda, v.use_phi);
+    }
+}
+
+#[test]
+fn butterfly_known_answers() {
+    for v in BUTTERFLY_VECTORS {
+        let mut catena = Catena::new_with_graph(v.g_high, Graph::DoubleButterfly).unwrap();
+        catena.set_salt(v.salt).unwrap();
+        let mut output = [0u8; NUM_HASH_BYTES];
+        catena.get(&mut output, v.password, v.g_low, v.lambda, v.use_phi).unwrap();
+        assert_eq!(output, v.output, "g_low {} g_high {} lambda {} phi {}", v.g_low, v.g_high, v.lambda, v.use_phi);
+    }
+}
+
+#[test]
+fn butterfly_version_ids() {
+    let mut skein512 = Skein512::new();
+    let mut id = [0u8; NUM_HASH_BYTES];
+    skein512.hash_native(&mut id, b"Catena-Butterfly-Skein512");
+    assert_eq!(id, BUTTERFLY_WITHOUT_PHI_VERSION_ID);
+    skein512.hash_native(&mut id, b"Catena-Butterfly-Skein512-Phi");
+    assert_eq!(id, BUTTERFLY_WITH_PHI_VERSION_ID);
+}
+
+#[test]
+fn server_relief_known_answers() {
+    for v in VECTORS {
+        let mut catena = new_catena(v, v.g_high);
+        let mut pre_final = [0u8; NUM_HASH_BYTES];
+        let mut output    = [0u8; NUM_HASH_BYTES];
+        catena.client_relief(&mut pre_final, v.password, v.g_low, v.lambda, v.use_phi).unwrap();
+        Catena::server_finish(&mut output, &pre_final, v.g_high).unwrap();
+        assert_eq!(output, v.output);
+    }
+}
+
+#[test]
+fn upgrade_known_answers() {
+    // Compute each vector at a lower garlic, then raise it to g_high without the password.
+    for v in VECTORS.iter().filter(|v| v.g_low < v.g_high) {
+        let old_g_high = v.g_high - 1;
+        let mut stored = [0u8; NUM_HASH_BYTES];
+        new_catena(v, old_g_high).get(&mut stored, v.password, v.g_low, v.lambda, v.use_phi).unwrap();
+        let mut catena = new_catena(v, v.g_high);
+        let mut output = [0u8; NUM_HASH_BYTES];
+        catena.upgrade(&mut output, &stored, old_g_high, v.g_high, v.lambda, v.use_phi).unwrap();
+        assert_eq!(output, v.output);
+    }
+}
+
+#[test]
+fn reused_instances_known_answers() {
+    // One instance, reconfigured for every vector in turn.
+    let mut catena = Catena::default();
+    for v in VECTORS {
+        catena.reconfigure(v.g_high).unwrap();
+        catena.set_salt(v.salt).unwrap();
+        let mut output = [0u8; NUM_HASH_BYTES];
+        catena.get(&mut output, v.password, v.g_low, v.lambda, v.use_phi).unwrap();
+        assert_eq!(output, v.output);
+    }
+}
+
+#[test]
+fn pooled_instances_known_answers() {
+    // A single pooled instance, wiped on every return and reconfigured on every acquisition.
+    let pool = CatenaPool::new(1, VECTORS[0].g_high, Graph::BitReversal).unwrap();
+    for v in VECTORS {
+        let mut catena = pool.acquire();
+        catena.reconfigure(v.g_high).unwrap();
+        catena.set_salt(v.salt).unwrap();
+        let mut output = [0u8; NUM_HASH_BYTES];
+        catena.get(&mut output, v.password, v.g_low, v.lambda, v.use_phi).unwrap();
+        assert_eq!(output, v.output);
+    }
+}
+
+#[test]
+fn kdf_known_answers() {
+    for v in KDF_VECTORS {
+        let mut output = [0u8; kdf::NUM_OUTPUT_BYTES];
+        kdf::multi_threaded(
+            &mut output,
+            &[9u8; NUM_SALT_BYTES],
+            b"hunter2",
+            v.thread_count,
+            v.thread_batch_size,
+            v.memory_low,
+            v.memory_high,
+            v.iterations,
+            v.use_phi
+        ).unwrap();
+        assert_eq!(output, v.output);
+    }
+}
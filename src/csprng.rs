@@ -22,16 +22,50 @@ use rssc::op::{SSC_secureZero, secure_zero, c_void};
 use rssc::rand::get_entropy;
 use skein512::Skein512;
 use skein512::NUM_HASH_WORDS;
+use std::time::{Duration, Instant};
 
 pub const NUM_SEED_BYTES:   usize = ubi512::NUM_HASH_BYTES;
 pub const NUM_BUFFER_BYTES: usize = NUM_SEED_BYTES * 2;
 
+/// When a Csprng automatically reseeds itself from the OS. Each trigger is checked before
+/// every request for output; a zero count or None disables it. The default disables all of
+/// them, so the Csprng only reseeds when asked to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReseedPolicy {
+    /// Reseed once this many bytes have been output since the last reseed.
+    pub max_bytes:    u64,
+    /// Reseed once get_bytes() has been called this many times since the last reseed.
+    pub max_calls:    u64,
+    /// Reseed once this much time has passed since the last reseed.
+    pub max_interval: Option<Duration>,
+    /// Reseed when the process ID changes, so that the parent and child of a fork() never
+    /// share an output stream.
+    pub detect_fork:  bool,
+}
+
+impl ReseedPolicy {
+    /// Fork detection, plus a reseed after every MiB of output or minute of use.
+    pub const fn recommended() -> ReseedPolicy {
+        ReseedPolicy {
+            max_bytes:    1 << 20,
+            max_calls:    0,
+            max_interval: Some(Duration::from_secs(60)),
+            detect_fork:  true,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone)]
 pub struct Csprng {
     pub skein512: Skein512,
     pub buffer:   [u8; NUM_BUFFER_BYTES],
     pub seed:     [u8; NUM_SEED_BYTES],
+    reseed_policy:      ReseedPolicy,
+    bytes_since_reseed: u64,
+    calls_since_reseed: u64,
+    last_reseed:        Option<Instant>,
+    pid:                u32,
 }
 
 impl Drop for Csprng {
@@ -59,12 +93,38 @@ impl Csprng {
     /// Create and return a new Csprng object. Initialize its @seed field with entropy from the OS.
     pub fn new() -> Csprng
     {
-        let mut csprng = Csprng {
+        Self::with_reseed_policy(ReseedPolicy::default())
+    }
+    /// Create a Csprng seeded from the OS that reseeds itself according to @reseed_policy.
+    pub fn with_reseed_policy(reseed_policy: ReseedPolicy) -> Csprng
+    {
+        let mut csprng = Self::from_seed([0u8; NUM_SEED_BYTES]);
+        csprng.reseed_policy = reseed_policy;
+        get_entropy(&mut csprng.seed);
+        csprng.reset_reseed_counters();
+        csprng
+    }
+    /// Create a deterministic Csprng (DRBG) whose output is fully determined by @seed, for
+    /// reproducible key generation from a master seed, simulations and test vectors.
+    /// The output stream is guaranteed to stay the same across versions of this crate:
+    /// each call to get_bytes() takes ceil(len / 64) steps, where one step replaces the seed
+    /// S by the first 64 bytes of the 128-byte Skein-512 hash of S and outputs the last 64,
+    /// the unused end of the final step being discarded. The stream therefore depends on how
+    /// output is requested: two 32-byte reads differ from one 64-byte read. get_random_u64()
+    /// is an 8-byte read, little-endian. Reseeding leaves the deterministic stream.
+    pub fn from_seed(mut seed: [u8; NUM_SEED_BYTES]) -> Csprng
+    {
+        let csprng = Csprng {
             skein512: Skein512::new(),
             buffer:   [0u8; NUM_BUFFER_BYTES],
-            seed:     [0u8; NUM_SEED_BYTES]
+            seed,
+            reseed_policy:      ReseedPolicy::default(),
+            bytes_since_reseed: 0,
+            calls_since_reseed: 0,
+            last_reseed:        None,
+            pid:                0,
         };
-        get_entropy(&mut csprng.seed);
+        secure_zero(&mut seed);
         csprng
     }
     /// Reseed the Csprng with the u8 bytes of @material.
@@ -85,6 +145,11 @@ impl Csprng {
         get_entropy(&mut self.buffer[NUM_SEED_BYTES..]);
         self.skein512.hash_native(&mut self.seed, &self.buffer);
         secure_zero(&mut self.buffer);
+        self.reset_reseed_counters();
+    }
+    pub fn get_reseed_policy(&self) -> ReseedPolicy
+    {
+        self.reseed_policy
     }
     /// Overwrite all the bytes of the slice @output with pseudorandom
     /// output bytes from the Csprng.
@@ -93,6 +158,9 @@ impl Csprng {
         if output.len() == 0 {
             return;
         }
+        self.reseed_if_due();
+        self.bytes_since_reseed = self.bytes_since_reseed.saturating_add(output.len() as u64);
+        self.calls_since_reseed = self.calls_since_reseed.saturating_add(1);
         let mut out = &mut output[..];
         while out.len() > NUM_SEED_BYTES {
             skein_hash_pre_configured!(self.skein512, &mut self.buffer, &self.seed);
@@ -107,6 +175,32 @@ impl Csprng {
         out.copy_from_slice(&self.buffer[NUM_SEED_BYTES..end_idx]);
         secure_zero(&mut self.buffer);
     }
+    /// Reseed from the OS if any trigger of the reseed policy has fired.
+    fn reseed_if_due(&mut self)
+    {
+        let policy = &self.reseed_policy;
+        let due = (policy.detect_fork && std::process::id() != self.pid)
+            || (policy.max_bytes != 0 && self.bytes_since_reseed >= policy.max_bytes)
+            || (policy.max_calls != 0 && self.calls_since_reseed >= policy.max_calls)
+            || match (policy.max_interval, self.last_reseed) {
+                (Some(interval), Some(last)) => last.elapsed() >= interval,
+                _ => false
+            };
+        if due {
+            self.reseed_from_os();
+        }
+    }
+    fn reset_reseed_counters(&mut self)
+    {
+        self.bytes_since_reseed = 0;
+        self.calls_since_reseed = 0;
+        if self.reseed_policy.max_interval.is_some() {
+            self.last_reseed = Some(Instant::now());
+        }
+        if self.reseed_policy.detect_fork {
+            self.pid = std::process::id();
+        }
+    }
     /// Generate a pseudorandom u64.
     pub fn get_random_u64(&mut self) -> u64
     {
@@ -120,7 +214,7 @@ impl Csprng {
         if max == 0u64 {
             return 0u64
         }
-        let max = max + 1
+        let max = max + 1;
         // r = 2^64 % max, computed without 128-bit math.
         // In unsigned arithmetic, 0u64.wrapping_sub(max) == 2^64 - max.
         // Then (2^64 - max) % max == 2^64 % max.
@@ -141,3 +235,213 @@ impl Csprng {
         range.0 + self.get_random_natural_num(range.1 - range.0)
     }
 }
+
+/// The seed of Csprng's SeedableRng implementation, zeroed when dropped.
+#[cfg(feature = "rand_core")]
+#[derive(Clone)]
+pub struct Seed(pub [u8; NUM_SEED_BYTES]);
+
+#[cfg(feature = "rand_core")]
+impl Default for Seed {
+    fn default() -> Self {
+        Seed([0u8; NUM_SEED_BYTES])
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl AsMut<[u8]> for Seed {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl Drop for Seed {
+    fn drop(&mut self) {
+        secure_zero(&mut self.0);
+    }
+}
+
+/// Little-endian integers and bytes from get_bytes(); never fails.
+#[cfg(feature = "rand_core")]
+impl rand_core::RngCore for Csprng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.get_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+    fn next_u64(&mut self) -> u64 {
+        self.get_random_u64()
+    }
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.get_bytes(dest);
+    }
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.get_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl rand_core::CryptoRng for Csprng {}
+
+/// Seeding gives the deterministic stream of Csprng::from_seed().
+#[cfg(feature = "rand_core")]
+impl rand_core::SeedableRng for Csprng {
+    type Seed = Seed;
+    fn from_seed(seed: Seed) -> Csprng {
+        Csprng::from_seed(seed.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_seed_stream_is_stable() {
+        let mut seed = [0u8; NUM_SEED_BYTES];
+        for (i, b) in seed.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        let mut rng = Csprng::from_seed(seed);
+        let mut output = [0u8; 96];
+        rng.get_bytes(&mut output);
+        assert_eq!(output, [
+            0x4e, 0x8f, 0xb3, 0x34, 0x01, 0x48, 0x4a, 0xc7,
+            0x2a, 0x4e, 0x4b, 0x8b, 0xea, 0xd9, 0x2b, 0xa6,
+            0xa6, 0x14, 0xc8, 0x3f, 0x25, 0x92, 0x5c, 0x4d,
+            0xd4, 0x3a, 0x86, 0x8c, 0x73, 0x56, 0x2a, 0x4f,
+            0x64, 0xe9, 0x85, 0xda, 0xd4, 0x41, 0x62, 0x4e,
+            0xbd, 0x87, 0x56, 0x17, 0x30, 0x94, 0xc9, 0x61,
+            0x58, 0x22, 0x83, 0xa1, 0x3f, 0x1a, 0xec, 0xc9,
+            0x5e, 0x96, 0xc2, 0x18, 0x48, 0x84, 0x08, 0x3d,
+            0x01, 0xa0, 0x82, 0x20, 0x45, 0x79, 0x0e, 0x35,
+            0x5d, 0x97, 0x1d, 0xf8, 0x69, 0x05, 0xbe, 0x73,
+            0xcf, 0x4b, 0x65, 0x88, 0x74, 0xdc, 0x1f, 0xa2,
+            0xd9, 0x47, 0xe9, 0xd6, 0x52, 0xeb, 0xa9, 0x6f,
+        ]);
+        assert_eq!(rng.get_random_u64(), 0x41ad4a0954848b34);
+
+        let mut output = [0u8; 32];
+        Csprng::from_seed([0u8; NUM_SEED_BYTES]).get_bytes(&mut output);
+        assert_eq!(output, [
+            0xad, 0x84, 0x8e, 0x5d, 0x79, 0x21, 0xcc, 0x4a,
+            0x47, 0x42, 0x79, 0xbc, 0x35, 0xea, 0x09, 0xb0,
+            0xc4, 0x4f, 0x8d, 0x51, 0xfa, 0xa5, 0x66, 0x59,
+            0x71, 0x6c, 0xc8, 0x19, 0x25, 0xd9, 0x67, 0xe9,
+        ]);
+    }
+
+    #[test]
+    fn from_seed_read_sizes() {
+        let seed = [0x42u8; NUM_SEED_BYTES];
+        let mut whole = [0u8; 100];
+        Csprng::from_seed(seed).get_bytes(&mut whole);
+        // Reads aligned to 64 bytes reproduce a single long read.
+        let mut parts = [0u8; 100];
+        let mut rng = Csprng::from_seed(seed);
+        rng.get_bytes(&mut parts[..64]);
+        rng.get_bytes(&mut parts[64..]);
+        assert_eq!(whole, parts);
+        // Unaligned reads discard the rest of their final step.
+        let mut rng = Csprng::from_seed(seed);
+        rng.get_bytes(&mut parts[..32]);
+        rng.get_bytes(&mut parts[32..64]);
+        assert_eq!(parts[..32], whole[..32]);
+        assert_ne!(parts[32..64], whole[32..64]);
+    }
+
+    #[cfg(feature = "rand_core")]
+    #[test]
+    fn rand_core_traits() {
+        use rand_core::{CryptoRng, RngCore, SeedableRng};
+
+        fn fill<R: RngCore + CryptoRng>(rng: &mut R, dest: &mut [u8]) {
+            rng.fill_bytes(dest);
+        }
+        let seed = [0x17u8; NUM_SEED_BYTES];
+        let mut expected = [0u8; 40];
+        Csprng::from_seed(seed).get_bytes(&mut expected);
+        let mut rng = <Csprng as SeedableRng>::from_seed(Seed(seed));
+        let mut output = [0u8; 40];
+        fill(&mut rng, &mut output);
+        assert_eq!(output, expected);
+
+        let mut a = Csprng::from_seed(seed);
+        let mut b = <Csprng as SeedableRng>::seed_from_u64(7);
+        let mut c = <Csprng as SeedableRng>::seed_from_u64(7);
+        assert_eq!(b.next_u64(), c.next_u64());
+        assert_eq!(a.next_u64(), Csprng::from_seed(seed).get_random_u64());
+        let mut d = Csprng::from_rng(&mut b).unwrap();
+        assert_ne!(d.next_u32(), c.next_u32());
+    }
+
+    /// A Csprng with @policy whose seed is then replaced by @seed, so that its output can be
+    /// compared with Csprng::from_seed(@seed) until it reseeds.
+    fn with_policy_and_seed(policy: ReseedPolicy, seed: [u8; NUM_SEED_BYTES]) -> Csprng {
+        let mut rng = Csprng::with_reseed_policy(policy);
+        rng.seed = seed;
+        rng
+    }
+
+    #[test]
+    fn reseeds_after_bytes_and_calls() {
+        let seed = [0x33u8; NUM_SEED_BYTES];
+        let mut twin = Csprng::from_seed(seed);
+        let policy = ReseedPolicy { max_calls: 2, ..ReseedPolicy::default() };
+        let mut rng = with_policy_and_seed(policy, seed);
+        assert_eq!(rng.get_random_u64(), twin.get_random_u64());
+        assert_eq!(rng.get_random_u64(), twin.get_random_u64());
+        assert_ne!(rng.get_random_u64(), twin.get_random_u64());
+
+        let mut twin = Csprng::from_seed(seed);
+        let policy = ReseedPolicy { max_bytes: 100, ..ReseedPolicy::default() };
+        let mut rng = with_policy_and_seed(policy, seed);
+        let mut a = [0u8; 64];
+        let mut b = [0u8; 64];
+        rng.get_bytes(&mut a);
+        twin.get_bytes(&mut b);
+        assert_eq!(a, b);
+        rng.get_bytes(&mut a);
+        twin.get_bytes(&mut b);
+        assert_eq!(a, b);
+        // 128 bytes have been output; the third request reseeds first.
+        rng.get_bytes(&mut a);
+        twin.get_bytes(&mut b);
+        assert_ne!(a, b);
+        assert_eq!(rng.bytes_since_reseed, 64);
+    }
+
+    #[test]
+    fn reseeds_after_interval_and_fork() {
+        let seed = [0x44u8; NUM_SEED_BYTES];
+        let policy = ReseedPolicy { max_interval: Some(Duration::from_secs(3600)), ..ReseedPolicy::default() };
+        let mut rng = with_policy_and_seed(policy, seed);
+        assert_eq!(rng.get_random_u64(), Csprng::from_seed(seed).get_random_u64());
+        // Pretend the interval has passed.
+        let mut rng = with_policy_and_seed(ReseedPolicy { max_interval: Some(Duration::ZERO), ..policy }, seed);
+        assert_ne!(rng.get_random_u64(), Csprng::from_seed(seed).get_random_u64());
+
+        let policy = ReseedPolicy { detect_fork: true, ..ReseedPolicy::default() };
+        let mut rng = with_policy_and_seed(policy, seed);
+        assert_eq!(rng.get_random_u64(), Csprng::from_seed(seed).get_random_u64());
+        // Pretend this process is a child of a fork.
+        let mut rng = with_policy_and_seed(policy, seed);
+        rng.pid = rng.pid.wrapping_add(1);
+        assert_ne!(rng.get_random_u64(), Csprng::from_seed(seed).get_random_u64());
+        assert_eq!(rng.pid, std::process::id());
+    }
+
+    #[test]
+    fn default_policy_never_reseeds() {
+        let seed = [0x55u8; NUM_SEED_BYTES];
+        let mut rng = with_policy_and_seed(ReseedPolicy::default(), seed);
+        rng.pid = rng.pid.wrapping_add(1);
+        let mut twin = Csprng::from_seed(seed);
+        for _ in 0..100 {
+            assert_eq!(rng.get_random_u64(), twin.get_random_u64());
+        }
+        assert_eq!(Csprng::new().get_reseed_policy(), ReseedPolicy::default());
+    }
+}
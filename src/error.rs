@@ -0,0 +1,64 @@
+/* *
+ * tsc - Implement Threefish, Skein, and CATENA cryptographic algorithms.
+ * Copyright (C) 2025 Stuart Calder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt;
+use crate::tf512::OcbError;
+
+/// The error type shared by the fallible operations of this crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// A parameter was out of range. The string names the offending parameter.
+    BadParameter(&'static str),
+    /// Memory allocation failed.
+    AllocFail,
+    /// The object was already initialized.
+    AlreadyInit,
+    /// The object must be initialized before this operation.
+    NotInit,
+    /// Authentication failed (e.g. an OCB tag did not match).
+    AuthFail,
+    /// A worker thread panicked.
+    ThreadPanic,
+    /// The operation was cancelled through its cancel flag.
+    Cancelled,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadParameter(what) => write!(f, "bad parameter: {}", what),
+            Error::AllocFail   => f.write_str("memory allocation failed"),
+            Error::AlreadyInit => f.write_str("already initialized"),
+            Error::NotInit     => f.write_str("not initialized"),
+            Error::AuthFail    => f.write_str("authentication failed"),
+            Error::ThreadPanic => f.write_str("a worker thread panicked"),
+            Error::Cancelled   => f.write_str("cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<OcbError> for Error {
+    fn from(e: OcbError) -> Self {
+        match e {
+            OcbError::TagMismatch   => Error::AuthFail,
+            OcbError::InvalidLength => Error::BadParameter("OCB input and output lengths differ"),
+        }
+    }
+}
@@ -19,10 +19,11 @@
 use std::slice;
 use std::thread;
 use std::cmp::min;
-use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::atomic::{AtomicI32, AtomicU8, Ordering};
 use crate::tf512;
 use crate::catena512;
 use crate::skein512;
+use crate::error::Error;
 
 use tf512::{
     NUM_BLOCK_BYTES,
@@ -34,28 +35,60 @@ use tf512::{
 use skein512::{
     Skein512
 };
-use catena512::Catena;
+
+pub mod skein;
+mod params;
+pub use params::{KdfParams, KdfParamsBuilder, MAX_GARLIC, MAX_THREADS};
+mod pool;
+pub use pool::KdfPool;
+use catena512::{Catena, Graph, Monitor};
 pub use catena512::NUM_SALT_BYTES;
 
 pub const NUM_OUTPUT_BYTES: usize = NUM_BLOCK_BYTES;
 
+/// How the per-thread Catena outputs ("lanes") are combined into the final output.
+/// The discriminant is the mode's version number, suitable for storing in file headers.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// XOR all lanes together. Ignores lane order and lets equal lanes cancel; kept for
+    /// compatibility with outputs derived by earlier versions.
+    #[default]
+    Xor    = 0,
+    /// Hash the parameter set and the lanes, in order, with Skein-512.
+    Hashed = 1,
+}
+
+/// Domain separation prefix of the Mode::Hashed combination.
+const HASHED_MODE_LABEL: &[u8] = b"tsc-kdf-lanes";
+
+/// Compute one lane. Its Catena, and so its graph memory, lives only as long as this call.
 fn one_thread(
+    output: &mut [u8; NUM_OUTPUT_BYTES],
+    input_salt: &[u8; NUM_SALT_BYTES],
+    input_password: &[u8],
+    thread_idx: u64,
+    params: &KdfParams,
+    monitor: &Monitor) -> Result<(), Error>
+{
+    let mut catena = Catena::new(params.memory_high)?;
+    lane(output, &mut catena, input_salt, input_password, thread_idx, params, monitor)
+}
+
+/// Compute lane @thread_idx with @catena, which must be allocated for @params.memory_high.
+fn lane(
     output: &mut [u8; NUM_OUTPUT_BYTES],
     catena: &mut Catena,
     input_salt: &[u8; NUM_SALT_BYTES],
     input_password: &[u8],
     thread_idx: u64,
-    memory_low: u8,
-    memory_high: u8,
-    iterations: u8,
-    use_phi: bool) -> Result<(), i32>
+    params: &KdfParams,
+    monitor: &Monitor) -> Result<(), Error>
 {
     const NUM_INPUT_BYTES: usize = NUM_SALT_BYTES + std::mem::size_of::<u64>();
 
     let mut input:    [u8; NUM_INPUT_BYTES] = [0u8; NUM_INPUT_BYTES];
 
-    // Initialize Catena.
-    catena.new_in_place(memory_high).unwrap();
     // Copy the input salt into the 32 bytes of @input.
     input[..NUM_SALT_BYTES].copy_from_slice(input_salt);
     // Get a little-endian version of the thread index.
@@ -67,16 +100,18 @@ fn one_thread(
         )
     });
     // Hash @input directly into @catena's salt buffer.
-    catena.skein512.hash(&mut catena.salt, &input);
-    catena.get(
+    catena.skein512.hash(&mut catena.salt[..NUM_SALT_BYTES], &input);
+    catena.get_monitored(
         output,
         input_password, /*TODO*/
-        memory_low,
-        iterations,
-        use_phi
+        params.memory_low,
+        params.iterations,
+        params.use_phi,
+        monitor
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn multi_threaded(
     output: &mut [u8; NUM_OUTPUT_BYTES],
     input_salt: &[u8; NUM_SALT_BYTES],
@@ -87,97 +122,256 @@ pub fn multi_threaded(
     memory_high: u8,
     iterations: u8,
     use_phi: bool,
-) -> Result<(), i32>
+) -> Result<(), Error>
+{
+    let params = KdfParams {
+        thread_count,
+        thread_batch_size,
+        memory_low,
+        memory_high,
+        iterations,
+        use_phi,
+        mode: Mode::Xor,
+    };
+    run_lanes(output, input_salt, input_password, &params, &Monitor::default())
+}
+
+/// The memory one thread of multi_threaded() holds: its graph memory plus its Catena.
+fn memory_per_thread(memory_high: u8) -> Result<usize, Error> {
+    if memory_high == 0 {
+        return Err(Error::BadParameter("memory_high must be at least 1"));
+    }
+    let params = catena512::Params {
+        g_low:   memory_high,
+        g_high:  memory_high,
+        lambda:  1,
+        use_phi: false,
+        graph:   Graph::BitReversal,
+    };
+    params.get_num_allocated_bytes()
+        .checked_add(std::mem::size_of::<Catena>())
+        .ok_or(Error::BadParameter("memory_high is out of range"))
+}
+
+/// The peak memory multi_threaded() needs: one Catena per thread of the largest batch.
+/// Each thread frees its memory as soon as its lane is done.
+pub fn estimate_memory(
+    thread_count: u64,
+    thread_batch_size: u64,
+    memory_high: u8) -> Result<usize, Error>
+{
+    if thread_count == 0 {
+        return Err(Error::BadParameter("thread_count must be at least 1"));
+    }
+    if thread_batch_size == 0 {
+        return Err(Error::BadParameter("thread_batch_size must be at least 1"));
+    }
+    let max_live_threads = usize::try_from(min(thread_count, thread_batch_size)).unwrap_or(usize::MAX);
+    memory_per_thread(memory_high)?
+        .checked_mul(max_live_threads)
+        .ok_or(Error::BadParameter("thread_count and memory_high need more memory than is addressable"))
+}
+
+/// multi_threaded_with_params() with @params.thread_batch_size derived from @memory_budget:
+/// as many threads run at once as fit in @memory_budget bytes. The batch size only schedules
+/// the lanes, so the result is identical to multi_threaded_with_params()'s. Fails if a single
+/// thread doesn't fit.
+pub fn multi_threaded_with_budget(
+    output: &mut [u8; NUM_OUTPUT_BYTES],
+    input_salt: &[u8; NUM_SALT_BYTES],
+    input_password: &[u8],
+    params: &KdfParams,
+    memory_budget: usize,
+) -> Result<(), Error>
+{
+    let thread_batch_size = (memory_budget / memory_per_thread(params.memory_high)?) as u64;
+    if thread_batch_size == 0 {
+        return Err(Error::BadParameter("memory_budget is too small for a single thread"));
+    }
+    let params = KdfParams {
+        thread_batch_size: min(thread_batch_size, params.thread_count.max(1)),
+        ..*params
+    };
+    multi_threaded_with_params(output, input_salt, input_password, &params)
+}
+
+/// multi_threaded_with_params(), expanded into any number of outputs of any length. Each
+/// entry of @outputs is a (label, output) pair; its output is filled by Skein-512 in KDF mode,
+/// keyed with the combined 64-byte secret and identified by its label, so differently labelled
+/// outputs are independent. The combined secret itself is never returned.
+pub fn multi_threaded_expand(
+    outputs: &mut [(&[u8], &mut [u8])],
+    input_salt: &[u8; NUM_SALT_BYTES],
+    input_password: &[u8],
+    params: &KdfParams,
+) -> Result<(), Error>
+{
+    let mut secret = [0u8; NUM_OUTPUT_BYTES];
+    multi_threaded_with_params(&mut secret, input_salt, input_password, params)?;
+    let prk = skein::Prk::from_bytes(&secret);
+    rssc::op::secure_zero(&mut secret);
+    skein::derive_subkeys(&prk, outputs);
+    Ok(())
+}
+
+/// multi_threaded_with_params() with the lanes combined according to @mode in place of
+/// @params.mode.
+pub fn multi_threaded_with_mode(
+    output: &mut [u8; NUM_OUTPUT_BYTES],
+    input_salt: &[u8; NUM_SALT_BYTES],
+    input_password: &[u8],
+    params: &KdfParams,
+    mode: Mode,
+) -> Result<(), Error>
+{
+    multi_threaded_with_params(output, input_salt, input_password, &KdfParams { mode, ..*params })
+}
+
+/// Derive @output from @input_password and @input_salt under @params, which are validated first.
+pub fn multi_threaded_with_params(
+    output: &mut [u8; NUM_OUTPUT_BYTES],
+    input_salt: &[u8; NUM_SALT_BYTES],
+    input_password: &[u8],
+    params: &KdfParams,
+) -> Result<(), Error>
+{
+    params.validate()?;
+    run_lanes(output, input_salt, input_password, params, &Monitor::default())
+}
+
+/// multi_threaded_with_params() on a dedicated thread, completing with the derived key.
+/// Dropping the future cancels the derivation; every worker wipes its memory and the key,
+/// if it was already derived, is zeroed.
+#[cfg(feature = "async")]
+pub fn derive_async(
+    params: &KdfParams,
+    input_password: &[u8],
+    input_salt: &[u8; NUM_SALT_BYTES]) -> crate::task::TaskFuture<skein::Prk>
 {
-    let thread_count = thread_count as usize;
-    let thread_batch_size = thread_batch_size as usize;
-    if thread_count == 0 { return Err(-1); }
+    let params = *params;
+    let mut password = input_password.to_vec();
+    let salt = *input_salt;
+    crate::task::spawn(move |cancel| {
+        let monitor = Monitor { cancel: Some(cancel), progress: None };
+        let mut output = [0u8; NUM_OUTPUT_BYTES];
+        let result = params.validate()
+            .and_then(|()| run_lanes(&mut output, &salt, &password, &params, &monitor))
+            .map(|()| skein::Prk::from_bytes(&output));
+        rssc::op::secure_zero(&mut output);
+        rssc::op::secure_zero(&mut password);
+        result
+    })
+}
+
+/// multi_threaded_with_params() under a Monitor. Every thread polls the cancel flag; progress
+/// is the average over all threads, including those of batches not yet started, and may be
+/// reported from any worker thread.
+pub fn multi_threaded_monitored(
+    output: &mut [u8; NUM_OUTPUT_BYTES],
+    input_salt: &[u8; NUM_SALT_BYTES],
+    input_password: &[u8],
+    params: &KdfParams,
+    monitor: &Monitor,
+) -> Result<(), Error>
+{
+    params.validate()?;
+    run_lanes(output, input_salt, input_password, params, monitor)
+}
+
+fn run_lanes(
+    output: &mut [u8; NUM_OUTPUT_BYTES],
+    input_salt: &[u8; NUM_SALT_BYTES],
+    input_password: &[u8],
+    params: &KdfParams,
+    monitor: &Monitor,
+) -> Result<(), Error>
+{
+    // Only the checks multi_threaded() always made; callers passing a KdfParams validate it
+    // first, while the positional entry points keep accepting what they always accepted.
+    if params.thread_count == 0 {
+        return Err(Error::BadParameter("thread_count must be at least 1"));
+    }
+    if params.thread_batch_size == 0 {
+        return Err(Error::BadParameter("thread_batch_size must be at least 1"));
+    }
+    let thread_count = params.thread_count as usize;
+    let thread_batch_size = params.thread_batch_size as usize;
 
-    let mut catenas: Vec<Catena> = vec![Catena::default(); thread_count];
     let mut outputs: Vec<[u8; NUM_BLOCK_BYTES]> = vec![[0u8; NUM_BLOCK_BYTES]; thread_count];
-    let mut errors: Vec<i32> = vec![0i32; thread_count];
+    let mut result: Result<(), Error> = Ok(());
+    let thread_percents: Vec<AtomicU8> = (0..thread_count).map(|_| AtomicU8::new(0)).collect();
+    let reported_percent = AtomicU8::new(0);
+    let thread_percents = &thread_percents;
+    let reported_percent = &reported_percent;
 
     let mut start = 0usize;
     while start < thread_count {
         let end = min(start + thread_batch_size, thread_count);
-        if end == start { break; }
-
-        // Drain the batch out of parent vectors (moves ownership; no clones)
-        let batch_catenas: Vec<Catena> = catenas.drain(start..end).collect();
-        let batch_outputs: Vec<[u8; NUM_BLOCK_BYTES]> = outputs.drain(start..end).collect();
+        if monitor.cancel.is_some_and(|c| c.load(Ordering::Relaxed)) {
+            result = Err(Error::Cancelled);
+            break;
+        }
 
-        // Spawn scoped threads so they can borrow salt/password
+        // Spawn scoped threads so they can borrow salt/password and their own output slots.
         thread::scope(|s| {
-            let mut handles = Vec::with_capacity(batch_outputs.len());
-            for (local_idx, (mut out_slot, mut cat_slot)) in batch_outputs.into_iter().zip(batch_catenas.into_iter()).enumerate() {
-                let salt_ref = input_salt;
-                let pwd_ref = input_password;
-                let mem_low = memory_low;
-                let mem_high = memory_high;
-                let iters = iterations;
-                let phi = use_phi;
-                let thread_idx = (start + local_idx) as u64;
-
-                handles.push(s.spawn(move || {
-                    let res = one_thread(
-                        &mut out_slot,
-                        &mut cat_slot,
-                        salt_ref,
-                        pwd_ref,
-                        thread_idx,
-                        mem_low,
-                        mem_high,
-                        iters,
-                        phi,
-                    );
-                    (local_idx, res, out_slot, cat_slot)
-                }));
-            }
-
-            // Collect results and put them into temporary vectors for reinsertion
-            let mut returned_outputs = vec![[0u8; NUM_BLOCK_BYTES]; end - start];
-            let mut returned_catenas = vec![Catena::default(); end - start]; // temporary placeholders to be overwritten immediately
+            let handles: Vec<_> = outputs[start..end].iter_mut()
+                .enumerate()
+                .map(|(local_idx, out_slot)| {
+                    let thread_idx = (start + local_idx) as u64;
+                    s.spawn(move || {
+                        // Record this thread's percent and report the overall average if it grew.
+                        let report = |percent: u8| {
+                            thread_percents[thread_idx as usize].store(percent, Ordering::Relaxed);
+                            let sum: usize = thread_percents.iter()
+                                .map(|p| p.load(Ordering::Relaxed) as usize)
+                                .sum();
+                            let overall = (sum / thread_count) as u8;
+                            if reported_percent.fetch_max(overall, Ordering::Relaxed) < overall {
+                                if let Some(progress) = monitor.progress {
+                                    progress(overall);
+                                }
+                            }
+                        };
+                        let thread_monitor = Monitor {
+                            cancel:   monitor.cancel,
+                            progress: Some(&report),
+                        };
+                        one_thread(
+                            out_slot,
+                            input_salt,
+                            input_password,
+                            thread_idx,
+                            params,
+                            &thread_monitor,
+                        )
+                    })
+                })
+                .collect();
 
+            // Join every thread of the batch, keeping the first error encountered.
             for handle in handles {
-                match handle.join() {
-                    Ok((local_idx, res, out_slot, cat_slot)) => {
-                        returned_outputs[local_idx] = out_slot;
-                        returned_catenas[local_idx] = cat_slot;
-                        errors[start + local_idx] = res.map(|()| 0).unwrap_or_else(|c| c);
-                    }
-                    Err(_) => {
-                        // thread panicked: zeroize whatever we can and mark error
-                        for block in &mut returned_outputs { rssc::op::secure_zero(block); }
-                        return; // scoped threads are joined; break out to outer flow to handle error
-                    }
+                let res = handle.join().unwrap_or(Err(Error::ThreadPanic));
+                if result.is_ok() {
+                    result = res;
                 }
             }
-
-            // Reinsert returned items into parent vectors at positions start..end
-            // Use extend + splice to preserve ordering
-            outputs.splice(start..start, returned_outputs.into_iter());
-            catenas.splice(start..start, returned_catenas.into_iter());
         });
-
+        if result.is_err() {
+            break;
+        }
         start = end;
     }
 
-    // If any thread failed, zeroize and return first non-zero code
-    if let Some(&code) = errors.iter().find(|&&e| e != 0) {
-        // zeroize outputs
+    // If any thread failed, zeroize and return the first error.
+    if let Err(e) = result {
         for block in &mut outputs {
             rssc::op::secure_zero(block);
         }
-        return Err(code);
+        return Err(e);
     }
 
     // Combine and copy out
-    for i in 1..thread_count {
-        for b in 0..NUM_BLOCK_BYTES {
-            outputs[0][b] ^= outputs[i][b];
-        }
-    }
-    output.copy_from_slice(&outputs[0][..NUM_OUTPUT_BYTES]);
+    combine_lanes(output, &mut outputs, params);
 
     // Zeroize temporaries
     for block in &mut outputs { rssc::op::secure_zero(block); }
@@ -185,3 +379,232 @@ pub fn multi_threaded(
     Ok(())
 }
 
+/// Combine the lane outputs @outputs, in lane order, according to @params.mode.
+/// @outputs may be modified.
+fn combine_lanes(
+    output: &mut [u8; NUM_OUTPUT_BYTES],
+    outputs: &mut [[u8; NUM_BLOCK_BYTES]],
+    params: &KdfParams)
+{
+    match params.mode {
+        Mode::Xor => {
+            for i in 1..outputs.len() {
+                for b in 0..NUM_BLOCK_BYTES {
+                    outputs[0][b] ^= outputs[i][b];
+                }
+            }
+            output.copy_from_slice(&outputs[0][..NUM_OUTPUT_BYTES]);
+        }
+        Mode::Hashed => {
+            let mut header = Vec::with_capacity(HASHED_MODE_LABEL.len() + 13);
+            header.extend_from_slice(HASHED_MODE_LABEL);
+            header.push(params.mode as u8);
+            header.extend_from_slice(&params.thread_count.to_le_bytes());
+            header.extend_from_slice(&[params.memory_low, params.memory_high, params.iterations, params.use_phi as u8]);
+            let mut parts: Vec<&[u8]> = Vec::with_capacity(outputs.len() + 1);
+            parts.push(&header);
+            parts.extend(outputs.iter().map(|lane| &lane[..]));
+            let ubi = &mut Skein512::new().ubi512;
+            ubi.threefish512.key[..NUM_KEY_WORDS].copy_from_slice(&skein512::NATIVE_INIT);
+            ubi.chain_message_parts(&parts);
+            ubi.chain_output_native(output);
+            rssc::op::secure_zero(&mut ubi.threefish512.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::sync::atomic::AtomicBool;
+
+    #[test]
+    fn positional_entry_points_keep_their_checks() {
+        let salt = [0x5Au8; NUM_SALT_BYTES];
+        // memory_low above memory_high is clamped to it, as it always was.
+        let mut clamped  = [0u8; NUM_OUTPUT_BYTES];
+        let mut expected = [0u8; NUM_OUTPUT_BYTES];
+        multi_threaded(&mut clamped,  &salt, b"password", 2, 2, 8, 6, 1, false).unwrap();
+        multi_threaded(&mut expected, &salt, b"password", 2, 2, 6, 6, 1, false).unwrap();
+        assert_eq!(clamped, expected);
+        let params = KdfParams { thread_count: 2, thread_batch_size: 2, memory_low: 8, memory_high: 6, iterations: 1, use_phi: false, mode: Mode::Xor };
+        assert!(multi_threaded_with_params(&mut clamped, &salt, b"password", &params).is_err());
+        // Neither is the thread count bounded by MAX_THREADS.
+        multi_threaded(&mut clamped, &salt, b"password", MAX_THREADS + 1, 64, 1, 1, 1, false).unwrap();
+        assert!(multi_threaded(&mut clamped, &salt, b"password", 0, 1, 1, 1, 1, false).is_err());
+        assert!(multi_threaded(&mut clamped, &salt, b"password", 1, 0, 1, 1, 1, false).is_err());
+    }
+
+    #[test]
+    fn monitored_matches_and_cancels() {
+        let salt = [0x5Au8; NUM_SALT_BYTES];
+        let mut expected = [0u8; NUM_OUTPUT_BYTES];
+        multi_threaded(&mut expected, &salt, b"password", 4, 2, 5, 6, 1, false).unwrap();
+
+        let params = KdfParams::builder()
+            .thread_count(4).thread_batch_size(2).memory_low(5).memory_high(6).iterations(1).mode(Mode::Xor)
+            .build().unwrap();
+        let reported = Mutex::new(Vec::new());
+        let record = |percent: u8| reported.lock().unwrap().push(percent);
+        let monitor = Monitor { cancel: None, progress: Some(&record) };
+        let mut output = [0u8; NUM_OUTPUT_BYTES];
+        multi_threaded_monitored(&mut output, &salt, b"password", &params, &monitor).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(reported.into_inner().unwrap().last(), Some(&100));
+
+        let cancel = AtomicBool::new(true);
+        let monitor = Monitor { cancel: Some(&cancel), progress: None };
+        assert_eq!(
+            multi_threaded_monitored(&mut output, &salt, b"password", &params, &monitor),
+            Err(Error::Cancelled)
+        );
+    }
+
+    #[test]
+    fn expand_labels_outputs() {
+        let salt = [0x5Au8; NUM_SALT_BYTES];
+        let mut enc_key = [0u8; 32];
+        let mut mac_key = [0u8; 64];
+        let mut iv      = [0u8; 16];
+        let mut outputs: [(&[u8], &mut [u8]); 3] = [
+            (b"encryption key", &mut enc_key),
+            (b"mac key",        &mut mac_key),
+            (b"iv",             &mut iv),
+        ];
+        let params = KdfParams::builder()
+            .thread_count(2).thread_batch_size(2).memory_low(5).memory_high(6).iterations(1).mode(Mode::Xor)
+            .build().unwrap();
+        multi_threaded_expand(&mut outputs, &salt, b"password", &params).unwrap();
+
+        let mut secret = [0u8; NUM_OUTPUT_BYTES];
+        multi_threaded(&mut secret, &salt, b"password", 2, 2, 5, 6, 1, false).unwrap();
+        let mut expected = [0u8; 64];
+        Skein512::new().kdf(&mut expected, &secret, b"mac key");
+        assert_eq!(mac_key, expected);
+        assert_ne!(&enc_key[..], &expected[..32]);
+        assert_ne!(mac_key, secret);
+        // Shorter outputs under the same label are not prefixes of longer ones.
+        let mut short = [0u8; 32];
+        Skein512::new().kdf(&mut short, &secret, b"mac key");
+        assert_ne!(&short[..], &mac_key[..32]);
+    }
+
+    #[test]
+    fn skein_kdf_accepts_any_key_length() {
+        // A 64-byte key is absorbed exactly as before; shorter keys are absorbed, not padded.
+        let key = [0x11u8; 64];
+        let mut expected = [0u8; 64];
+        let mut output   = [0u8; 64];
+        let mut ubi = Skein512::new().ubi512;
+        ubi.chain_key_u8(&key);
+        ubi.chain_config(64 * 8);
+        ubi.chain_kdf(b"label");
+        ubi.chain_output(&mut expected);
+        Skein512::new().kdf(&mut output, &key, b"label");
+        assert_eq!(output, expected);
+
+        let mut short_key = [0u8; 64];
+        short_key[..32].copy_from_slice(&key[..32]);
+        let mut short = [0u8; 64];
+        let mut padded = [0u8; 64];
+        Skein512::new().kdf(&mut short, &key[..32], b"label");
+        Skein512::new().kdf(&mut padded, &short_key, b"label");
+        assert_ne!(short, padded);
+        assert_ne!(short, output);
+    }
+
+    #[test]
+    fn hashed_mode_binds_lanes_and_parameters() {
+        let salt = [0x5Au8; NUM_SALT_BYTES];
+        let mut xor = [0u8; NUM_OUTPUT_BYTES];
+        let mut hashed = [0u8; NUM_OUTPUT_BYTES];
+        let params = KdfParams::builder()
+            .thread_count(3).thread_batch_size(3).memory_low(5).memory_high(6).iterations(1)
+            .build().unwrap();
+        multi_threaded(&mut xor, &salt, b"password", 3, 3, 5, 6, 1, false).unwrap();
+        multi_threaded_with_mode(&mut hashed, &salt, b"password", &params, Mode::Xor).unwrap();
+        assert_eq!(xor, hashed);
+
+        multi_threaded_with_mode(&mut hashed, &salt, b"password", &params, Mode::Hashed).unwrap();
+        assert_ne!(xor, hashed);
+        // The batch size only schedules the lanes.
+        let mut other = [0u8; NUM_OUTPUT_BYTES];
+        multi_threaded_with_mode(&mut other, &salt, b"password", &KdfParams { thread_batch_size: 1, ..params }, Mode::Hashed).unwrap();
+        assert_eq!(other, hashed);
+
+        // A single XOR lane is output as is; hashing still transforms it.
+        let mut one_xor = [0u8; NUM_OUTPUT_BYTES];
+        let mut one_hashed = [0u8; NUM_OUTPUT_BYTES];
+        multi_threaded(&mut one_xor, &salt, b"password", 1, 1, 5, 6, 1, false).unwrap();
+        let one = KdfParams { thread_count: 1, thread_batch_size: 1, ..params };
+        multi_threaded_with_mode(&mut one_hashed, &salt, b"password", &one, Mode::Hashed).unwrap();
+        assert_ne!(one_xor, one_hashed);
+    }
+
+    #[test]
+    fn params_match_positional_arguments() {
+        let salt = [0x5Au8; NUM_SALT_BYTES];
+        let params = KdfParams::builder()
+            .thread_count(3)
+            .thread_batch_size(2)
+            .memory_low(5)
+            .memory_high(6)
+            .iterations(1)
+            .use_phi(true)
+            .build()
+            .unwrap();
+        let mut expected = [0u8; NUM_OUTPUT_BYTES];
+        // @mode takes the place of the mode in the parameters.
+        multi_threaded_with_mode(&mut expected, &salt, b"password", &KdfParams { mode: Mode::Xor, ..params }, Mode::Hashed).unwrap();
+        let mut output = [0u8; NUM_OUTPUT_BYTES];
+        multi_threaded_with_params(&mut output, &salt, b"password", &params).unwrap();
+        assert_eq!(output, expected);
+        multi_threaded(&mut expected, &salt, b"password", 3, 2, 5, 6, 1, true).unwrap();
+        multi_threaded_with_params(&mut output, &salt, b"password", &KdfParams { mode: Mode::Xor, ..params }).unwrap();
+        assert_eq!(output, expected);
+        let invalid = KdfParams { memory_low: 7, ..params };
+        assert!(multi_threaded_with_params(&mut output, &salt, b"password", &invalid).is_err());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn derive_async_matches_and_cancels() {
+        use crate::task::block_on;
+        let salt = [0x5Au8; NUM_SALT_BYTES];
+        let params = KdfParams::builder().thread_count(2).memory(6).iterations(1).build().unwrap();
+        let mut expected = [0u8; NUM_OUTPUT_BYTES];
+        multi_threaded_with_params(&mut expected, &salt, b"password", &params).unwrap();
+        let prk = block_on(derive_async(&params, b"password", &salt)).unwrap();
+        assert_eq!(prk.as_bytes(), &expected);
+        // Dropping an unfinished derivation must not block or leak its thread's result.
+        let slow = KdfParams::builder().thread_count(1).memory(16).iterations(4).build().unwrap();
+        drop(derive_async(&slow, b"password", &salt));
+    }
+
+    #[test]
+    fn budget_sets_batch_size() {
+        let salt = [0x5Au8; NUM_SALT_BYTES];
+        let per_thread = estimate_memory(1, 1, 6).unwrap();
+        assert_eq!(estimate_memory(5, 2, 6).unwrap(), per_thread * 2);
+        assert_eq!(estimate_memory(3, 8, 6).unwrap(), per_thread * 3);
+        assert!(estimate_memory(0, 1, 6).is_err());
+        assert!(estimate_memory(u64::MAX, u64::MAX, 60).is_err());
+
+        let params = KdfParams::builder()
+            .thread_count(5).thread_batch_size(5).memory_low(5).memory_high(6).iterations(1).mode(Mode::Xor)
+            .build().unwrap();
+        let mut expected = [0u8; NUM_OUTPUT_BYTES];
+        multi_threaded(&mut expected, &salt, b"password", 5, 5, 5, 6, 1, false).unwrap();
+        for budget in [per_thread, per_thread * 2 + 1, usize::MAX] {
+            let mut output = [0u8; NUM_OUTPUT_BYTES];
+            multi_threaded_with_budget(&mut output, &salt, b"password", &params, budget).unwrap();
+            assert_eq!(output, expected);
+        }
+        let mut output = [0u8; NUM_OUTPUT_BYTES];
+        assert_eq!(
+            multi_threaded_with_budget(&mut output, &salt, b"password", &params, per_thread - 1),
+            Err(Error::BadParameter("memory_budget is too small for a single thread"))
+        );
+    }
+}
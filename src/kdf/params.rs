@@ -0,0 +1,347 @@
+/* *
+ * tsc - Implement Threefish, Skein, and CATENA cryptographic algorithms.
+ * Copyright (C) 2025 Stuart Calder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! KdfParams: the full parameter set of kdf::multi_threaded(), with validation, named
+//! profiles and encodings for storing the parameters next to what they protect.
+
+use std::fmt;
+use std::str::FromStr;
+use crate::error::Error;
+use crate::phc;
+use super::{Mode, estimate_memory};
+
+/// The largest garlic memory_low and memory_high may take.
+pub const MAX_GARLIC: u8 = 63;
+/// The largest thread_count may take. Each thread is a lane with its own output block and job,
+/// so stored parameters must not be able to ask for an unbounded number of them.
+pub const MAX_THREADS: u64 = 1024;
+/// The version byte leading the binary encoding.
+pub const ENCODING_VERSION: u8 = 1;
+/// The length of KdfParams::to_bytes().
+pub const NUM_ENCODED_BYTES: usize = 1 + 1 + 8 + 8 + 1 + 1 + 1 + 1;
+/// The identifier leading the string encoding.
+pub const STRING_ID: &str = "tsc-kdf";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KdfParams {
+    pub thread_count:      u64,
+    /// How many threads run at once. Doesn't affect the output.
+    pub thread_batch_size: u64,
+    pub memory_low:        u8,
+    pub memory_high:       u8,
+    pub iterations:        u8,
+    pub use_phi:           bool,
+    pub mode:              Mode,
+}
+
+/// Builds a KdfParams, starting from KdfParams::default(), and validates it in build().
+#[derive(Clone, Copy, Debug)]
+pub struct KdfParamsBuilder {
+    params: KdfParams,
+}
+
+impl KdfParams {
+    /// For frequent, latency-sensitive unlocks: one thread, 8 MiB.
+    pub const fn interactive() -> KdfParams {
+        KdfParams {
+            thread_count:      1,
+            thread_batch_size: 1,
+            memory_low:        17,
+            memory_high:       17,
+            iterations:        2,
+            use_phi:           false,
+            mode:              Mode::Hashed,
+        }
+    }
+    /// For stored secrets: four threads of 64 MiB each.
+    pub const fn sensitive() -> KdfParams {
+        KdfParams {
+            thread_count:      4,
+            thread_batch_size: 4,
+            memory_low:        20,
+            memory_high:       20,
+            iterations:        2,
+            use_phi:           false,
+            mode:              Mode::Hashed,
+        }
+    }
+    /// For long-term secrets: eight threads of 256 MiB each, four at a time, with phi.
+    pub const fn paranoid() -> KdfParams {
+        KdfParams {
+            thread_count:      8,
+            thread_batch_size: 4,
+            memory_low:        22,
+            memory_high:       22,
+            iterations:        3,
+            use_phi:           true,
+            mode:              Mode::Hashed,
+        }
+    }
+    pub fn builder() -> KdfParamsBuilder {
+        KdfParamsBuilder { params: KdfParams::default() }
+    }
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.thread_count == 0 {
+            return Err(Error::BadParameter("thread_count must be at least 1"));
+        }
+        if self.thread_count > MAX_THREADS {
+            return Err(Error::BadParameter("thread_count must not exceed MAX_THREADS"));
+        }
+        if self.thread_batch_size == 0 {
+            return Err(Error::BadParameter("thread_batch_size must be at least 1"));
+        }
+        if self.memory_low == 0 {
+            return Err(Error::BadParameter("memory_low must be at least 1"));
+        }
+        if self.memory_low > self.memory_high {
+            return Err(Error::BadParameter("memory_low must not exceed memory_high"));
+        }
+        if self.memory_high > MAX_GARLIC {
+            return Err(Error::BadParameter("memory_high must not exceed MAX_GARLIC"));
+        }
+        if self.iterations == 0 {
+            return Err(Error::BadParameter("iterations must be at least 1"));
+        }
+        self.estimate_memory().map(|_| ())
+    }
+    /// The peak memory multi_threaded_with_params() needs for these parameters.
+    pub fn estimate_memory(&self) -> Result<usize, Error> {
+        estimate_memory(self.thread_count, self.thread_batch_size, self.memory_high)
+    }
+    pub fn to_bytes(&self) -> [u8; NUM_ENCODED_BYTES] {
+        let mut bytes = [0u8; NUM_ENCODED_BYTES];
+        bytes[0] = ENCODING_VERSION;
+        bytes[1] = self.mode as u8;
+        bytes[2..10].copy_from_slice(&self.thread_count.to_le_bytes());
+        bytes[10..18].copy_from_slice(&self.thread_batch_size.to_le_bytes());
+        bytes[18] = self.memory_low;
+        bytes[19] = self.memory_high;
+        bytes[20] = self.iterations;
+        bytes[21] = self.use_phi as u8;
+        bytes
+    }
+    /// Parse and validate the output of to_bytes().
+    pub fn from_bytes(bytes: &[u8]) -> Result<KdfParams, Error> {
+        const MALFORMED: Error = Error::BadParameter("malformed encoded KDF parameters");
+        if bytes.len() != NUM_ENCODED_BYTES || bytes[0] != ENCODING_VERSION || bytes[21] > 1 {
+            return Err(MALFORMED);
+        }
+        let params = KdfParams {
+            thread_count:      u64::from_le_bytes(bytes[2..10].try_into().unwrap()),
+            thread_batch_size: u64::from_le_bytes(bytes[10..18].try_into().unwrap()),
+            memory_low:        bytes[18],
+            memory_high:       bytes[19],
+            iterations:        bytes[20],
+            use_phi:           bytes[21] == 1,
+            mode:              mode_from_u8(bytes[1]).ok_or(MALFORMED)?,
+        };
+        params.validate()?;
+        Ok(params)
+    }
+}
+
+/// The sensitive() profile.
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams::sensitive()
+    }
+}
+
+impl KdfParamsBuilder {
+    pub fn thread_count(mut self, thread_count: u64) -> Self {
+        self.params.thread_count = thread_count;
+        self
+    }
+    pub fn thread_batch_size(mut self, thread_batch_size: u64) -> Self {
+        self.params.thread_batch_size = thread_batch_size;
+        self
+    }
+    /// Set memory_low and memory_high together.
+    pub fn memory(mut self, garlic: u8) -> Self {
+        self.params.memory_low  = garlic;
+        self.params.memory_high = garlic;
+        self
+    }
+    pub fn memory_low(mut self, memory_low: u8) -> Self {
+        self.params.memory_low = memory_low;
+        self
+    }
+    pub fn memory_high(mut self, memory_high: u8) -> Self {
+        self.params.memory_high = memory_high;
+        self
+    }
+    pub fn iterations(mut self, iterations: u8) -> Self {
+        self.params.iterations = iterations;
+        self
+    }
+    pub fn use_phi(mut self, use_phi: bool) -> Self {
+        self.params.use_phi = use_phi;
+        self
+    }
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.params.mode = mode;
+        self
+    }
+    pub fn build(self) -> Result<KdfParams, Error> {
+        self.params.validate()?;
+        Ok(self.params)
+    }
+}
+
+fn mode_from_u8(value: u8) -> Option<Mode> {
+    match value {
+        0 => Some(Mode::Xor),
+        1 => Some(Mode::Hashed),
+        _ => None
+    }
+}
+
+/// "$tsc-kdf$v=1,t=4,b=4,ml=20,mh=20,i=2,phi=0", where v is the Mode.
+impl fmt::Display for KdfParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "${}$v={},t={},b={},ml={},mh={},i={},phi={}",
+            STRING_ID,
+            self.mode as u8,
+            self.thread_count,
+            self.thread_batch_size,
+            self.memory_low,
+            self.memory_high,
+            self.iterations,
+            self.use_phi as u8
+        )
+    }
+}
+
+impl FromStr for KdfParams {
+    type Err = Error;
+    /// Parse and validate the Display form. Every field must appear exactly once, without
+    /// signs or leading zeros, so each parameter set has exactly one encoding.
+    fn from_str(encoded: &str) -> Result<KdfParams, Error> {
+        const MALFORMED: Error = Error::BadParameter("malformed KDF parameter string");
+        let fields: Vec<&str> = encoded.split('$').collect();
+        if fields.len() != 3 || !fields[0].is_empty() || fields[1] != STRING_ID {
+            return Err(MALFORMED);
+        }
+        let [v, t, b, ml, mh, i, phi] = phc::parse_params(fields[2], &["v", "t", "b", "ml", "mh", "i", "phi"])
+            .ok_or(MALFORMED)?;
+        let small = |x: u64| u8::try_from(x).map_err(|_| MALFORMED);
+        if phi > 1 {
+            return Err(MALFORMED);
+        }
+        let params = KdfParams {
+            thread_count:      t,
+            thread_batch_size: b,
+            memory_low:        small(ml)?,
+            memory_high:       small(mh)?,
+            iterations:        small(i)?,
+            use_phi:           phi == 1,
+            mode:              mode_from_u8(small(v)?).ok_or(MALFORMED)?,
+        };
+        params.validate()?;
+        Ok(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_validates() {
+        let params = KdfParams::builder()
+            .thread_count(3)
+            .thread_batch_size(2)
+            .memory_low(5)
+            .memory_high(7)
+            .iterations(1)
+            .mode(Mode::Xor)
+            .build()
+            .unwrap();
+        assert_eq!(params.thread_count, 3);
+        assert_eq!((params.memory_low, params.memory_high), (5, 7));
+        assert_eq!(
+            KdfParams::builder().memory_low(8).memory_high(7).build(),
+            Err(Error::BadParameter("memory_low must not exceed memory_high"))
+        );
+        assert_eq!(
+            KdfParams::builder().memory(64).build(),
+            Err(Error::BadParameter("memory_high must not exceed MAX_GARLIC"))
+        );
+        assert!(KdfParams::builder().thread_count(0).build().is_err());
+        assert!(KdfParams::builder().thread_count(MAX_THREADS).thread_batch_size(1).memory(1).build().is_ok());
+        assert_eq!(
+            KdfParams::builder().thread_count(MAX_THREADS + 1).thread_batch_size(1).memory(1).build(),
+            Err(Error::BadParameter("thread_count must not exceed MAX_THREADS"))
+        );
+        assert!(KdfParams::builder().thread_batch_size(0).build().is_err());
+        assert!(KdfParams::builder().memory(0).build().is_err());
+        assert!(KdfParams::builder().iterations(0).build().is_err());
+        // Validates, but needs more memory than can be addressed.
+        assert!(KdfParams::builder().memory(63).build().is_err());
+        for profile in [KdfParams::interactive(), KdfParams::sensitive(), KdfParams::paranoid()] {
+            profile.validate().unwrap();
+        }
+        assert_eq!(KdfParams::default(), KdfParams::sensitive());
+    }
+
+    #[test]
+    fn encodings_round_trip() {
+        for params in [KdfParams::interactive(), KdfParams::sensitive(), KdfParams::paranoid(),
+                       KdfParams::builder().memory_low(3).memory_high(9).mode(Mode::Xor).build().unwrap()] {
+            assert_eq!(KdfParams::from_bytes(&params.to_bytes()).unwrap(), params);
+            assert_eq!(params.to_string().parse::<KdfParams>().unwrap(), params);
+        }
+        assert_eq!(KdfParams::sensitive().to_string(), "$tsc-kdf$v=1,t=4,b=4,ml=20,mh=20,i=2,phi=0");
+    }
+
+    #[test]
+    fn rejects_malformed_encodings() {
+        let mut bytes = KdfParams::sensitive().to_bytes();
+        assert!(KdfParams::from_bytes(&bytes[..NUM_ENCODED_BYTES - 1]).is_err());
+        bytes[0] = ENCODING_VERSION + 1;
+        assert!(KdfParams::from_bytes(&bytes).is_err());
+        let mut bytes = KdfParams::sensitive().to_bytes();
+        bytes[1] = 2;
+        assert!(KdfParams::from_bytes(&bytes).is_err());
+        let mut bytes = KdfParams::sensitive().to_bytes();
+        bytes[18] = 21;
+        assert!(KdfParams::from_bytes(&bytes).is_err());
+        let mut bytes = KdfParams::sensitive().to_bytes();
+        bytes[2..10].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(KdfParams::from_bytes(&bytes).is_err());
+
+        for bad in [
+            "",
+            "$tsc-kdf$v=1,t=4,b=4,ml=20,mh=20,i=2",
+            "$tsc-kdf$v=1,t=4,b=4,ml=20,mh=20,i=2,phi=0,phi=0",
+            "$tsc-kdf$v=1,t=04,b=4,ml=20,mh=20,i=2,phi=0",
+            "$tsc-kdf$v=1,t=+4,b=4,ml=20,mh=20,i=2,phi=0",
+            "$tsc-kdf$v=2,t=4,b=4,ml=20,mh=20,i=2,phi=0",
+            "$tsc-kdf$v=1,t=4,b=4,ml=20,mh=256,i=2,phi=0",
+            "$tsc-kdf$v=1,t=4,b=4,ml=21,mh=20,i=2,phi=0",
+            "$tsc-kdf$v=1,t=4,b=4,ml=20,mh=20,i=2,phi=2",
+            "$catena$v=1,t=4,b=4,ml=20,mh=20,i=2,phi=0",
+            "$tsc-kdf$v=1,t=4,b=4,ml=20,mh=20,i=2,phi=0$",
+            "$tsc-kdf$v=1,t=18446744073709551615,b=1,ml=1,mh=1,i=1,phi=0",
+            "$tsc-kdf$v=1,t=4294967296,b=1,ml=1,mh=1,i=1,phi=0",
+        ] {
+            assert!(bad.parse::<KdfParams>().is_err(), "{}", bad);
+        }
+    }
+}
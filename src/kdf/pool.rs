@@ -0,0 +1,257 @@
+/* *
+ * tsc - Implement Threefish, Skein, and CATENA cryptographic algorithms.
+ * Copyright (C) 2025 Stuart Calder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! KdfPool: persistent worker threads for repeated KDF derivations.
+
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::panic::{self, AssertUnwindSafe};
+use crate::catena512::{Catena, Monitor};
+use crate::error::Error;
+use crate::tf512::NUM_BLOCK_BYTES;
+use super::{KdfParams, NUM_OUTPUT_BYTES, NUM_SALT_BYTES, combine_lanes, lane};
+
+/// The inputs shared by all lanes of one derivation. The password is zeroed when the last
+/// lane is done with it.
+struct JobInput {
+    salt:     [u8; NUM_SALT_BYTES],
+    password: Vec<u8>,
+    params:   KdfParams,
+}
+
+impl Drop for JobInput {
+    fn drop(&mut self) {
+        rssc::op::secure_zero(&mut self.password);
+        rssc::op::secure_zero(&mut self.salt);
+    }
+}
+
+type LaneResult = (usize, Result<[u8; NUM_BLOCK_BYTES], Error>);
+
+struct LaneJob {
+    input:      Arc<JobInput>,
+    thread_idx: u64,
+    result:     mpsc::Sender<LaneResult>,
+}
+
+/// A fixed set of worker threads, each keeping its own Catena allocated between jobs.
+/// Derivations are split into lanes that are served through a shared channel, so any number
+/// of threads may call derive() concurrently. A worker wipes its graph memory after every lane,
+/// and reallocates it only when a job needs a different memory_high.
+/// The outputs are identical to those of multi_threaded_with_params().
+pub struct KdfPool {
+    jobs:    Option<mpsc::Sender<LaneJob>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl KdfPool {
+    /// Start @num_workers workers with graph memory pre-allocated for @memory_high.
+    pub fn new(num_workers: usize, memory_high: u8) -> Result<KdfPool, Error> {
+        if num_workers == 0 {
+            return Err(Error::BadParameter("num_workers must be at least 1"));
+        }
+        let (sender, receiver) = mpsc::channel::<LaneJob>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut pool = KdfPool { jobs: Some(sender), workers: Vec::with_capacity(num_workers) };
+        for _ in 0..num_workers {
+            let catena   = Catena::new(memory_high)?;
+            let receiver = Arc::clone(&receiver);
+            let worker = thread::Builder::new()
+                .name("tsc-kdf-worker".to_string())
+                .spawn(move || worker_loop(catena, receiver))
+                .map_err(|_| Error::AllocFail)?;
+            pool.workers.push(worker);
+        }
+        Ok(pool)
+    }
+    pub fn num_workers(&self) -> usize {
+        self.workers.len()
+    }
+    /// Derive @output from @input_password and @input_salt under @params, blocking until done.
+    /// At most num_workers() lanes run at once; @params.thread_batch_size is not used.
+    pub fn derive(
+        &self,
+        output: &mut [u8; NUM_OUTPUT_BYTES],
+        input_salt: &[u8; NUM_SALT_BYTES],
+        input_password: &[u8],
+        params: &KdfParams) -> Result<(), Error>
+    {
+        params.validate()?;
+        let thread_count = usize::try_from(params.thread_count)
+            .map_err(|_| Error::BadParameter("thread_count is too large"))?;
+        let input = Arc::new(JobInput {
+            salt:     *input_salt,
+            password: input_password.to_vec(),
+            params:   *params,
+        });
+        let (result_sender, result_receiver) = mpsc::channel::<LaneResult>();
+        let jobs = self.jobs.as_ref().unwrap();
+        for thread_idx in 0..params.thread_count {
+            let job = LaneJob {
+                input:      Arc::clone(&input),
+                thread_idx,
+                result:     result_sender.clone(),
+            };
+            jobs.send(job).map_err(|_| Error::ThreadPanic)?;
+        }
+        drop(result_sender);
+        drop(input);
+
+        let mut outputs: Vec<[u8; NUM_BLOCK_BYTES]> = vec![[0u8; NUM_BLOCK_BYTES]; thread_count];
+        let mut result: Result<(), Error> = Ok(());
+        for _ in 0..thread_count {
+            match result_receiver.recv() {
+                Ok((idx, Ok(mut lane_output))) => {
+                    outputs[idx] = lane_output;
+                    rssc::op::secure_zero(&mut lane_output);
+                }
+                Ok((_, Err(e))) => {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+                // Every sender is gone without reporting: a worker died holding a lane.
+                Err(_) => {
+                    if result.is_ok() {
+                        result = Err(Error::ThreadPanic);
+                    }
+                    break;
+                }
+            }
+        }
+        if result.is_ok() {
+            combine_lanes(output, &mut outputs, params);
+        }
+        for block in &mut outputs {
+            rssc::op::secure_zero(block);
+        }
+        result
+    }
+}
+
+impl Drop for KdfPool {
+    fn drop(&mut self) {
+        // Closing the job channel ends every worker loop; each worker's Catena is zeroed on drop.
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker_loop(mut catena: Catena, receiver: Arc<Mutex<mpsc::Receiver<LaneJob>>>) {
+    loop {
+        let job = {
+            let receiver = receiver.lock().unwrap_or_else(|e| e.into_inner());
+            match receiver.recv() {
+                Ok(job) => job,
+                Err(_)  => return,
+            }
+        };
+        let result = run_lane(&mut catena, &job);
+        let _ = job.result.send((job.thread_idx as usize, result));
+    }
+}
+
+/// Compute the lane of @job with @catena, then wipe @catena so that nothing of the job, the
+/// lane output included, stays in the worker until its next job.
+fn run_lane(catena: &mut Catena, job: &LaneJob) -> Result<[u8; NUM_BLOCK_BYTES], Error> {
+    let mut lane_output = [0u8; NUM_BLOCK_BYTES];
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        let input = &job.input;
+        catena.reconfigure(input.params.memory_high)?;
+        lane(
+            &mut lane_output,
+            catena,
+            &input.salt,
+            &input.password,
+            job.thread_idx,
+            &input.params,
+            &Monitor::default()
+        )
+    }));
+    catena.wipe();
+    let result = match outcome {
+        Ok(Ok(()))  => Ok(lane_output),
+        Ok(Err(e))  => Err(e),
+        Err(_)      => Err(Error::ThreadPanic),
+    };
+    rssc::op::secure_zero(&mut lane_output);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kdf::{multi_threaded_with_params, Mode};
+
+    #[test]
+    fn pool_matches_multi_threaded() {
+        let pool = KdfPool::new(2, 6).unwrap();
+        assert_eq!(pool.num_workers(), 2);
+        let salt = [0x5Au8; NUM_SALT_BYTES];
+        let cases = [
+            KdfParams::builder().thread_count(3).thread_batch_size(3).memory(6).iterations(1).mode(Mode::Xor).build().unwrap(),
+            KdfParams::builder().thread_count(5).thread_batch_size(1).memory_low(3).memory_high(6).iterations(2).use_phi(true).build().unwrap(),
+            // A different memory_high makes the workers reallocate.
+            KdfParams::builder().thread_count(2).thread_batch_size(2).memory(5).iterations(1).build().unwrap(),
+        ];
+        for params in &cases {
+            let mut expected = [0u8; NUM_OUTPUT_BYTES];
+            let mut output   = [0u8; NUM_OUTPUT_BYTES];
+            multi_threaded_with_params(&mut expected, &salt, b"password", params).unwrap();
+            pool.derive(&mut output, &salt, b"password", params).unwrap();
+            assert_eq!(output, expected);
+        }
+        let invalid = KdfParams { memory_low: 7, ..cases[0] };
+        let mut output = [0u8; NUM_OUTPUT_BYTES];
+        assert!(pool.derive(&mut output, &salt, b"password", &invalid).is_err());
+    }
+
+    #[test]
+    fn workers_wipe_their_catena_between_jobs() {
+        let params = KdfParams::builder().thread_count(2).memory(5).iterations(1).use_phi(true).build().unwrap();
+        let (sender, _receiver) = mpsc::channel::<LaneResult>();
+        let job = LaneJob {
+            input: Arc::new(JobInput { salt: [0x5Au8; NUM_SALT_BYTES], password: b"password".to_vec(), params }),
+            thread_idx: 1,
+            result: sender,
+        };
+        let mut catena = Catena::new(5).unwrap();
+        assert!(run_lane(&mut catena, &job).is_ok());
+        assert!(catena.is_wiped());
+    }
+
+    #[test]
+    fn pool_serves_concurrent_callers() {
+        let pool = KdfPool::new(3, 5).unwrap();
+        let params = KdfParams::builder().thread_count(2).memory(5).iterations(1).build().unwrap();
+        thread::scope(|s| {
+            for i in 0..4u8 {
+                let pool = &pool;
+                s.spawn(move || {
+                    let salt = [i; NUM_SALT_BYTES];
+                    let mut expected = [0u8; NUM_OUTPUT_BYTES];
+                    let mut output   = [0u8; NUM_OUTPUT_BYTES];
+                    multi_threaded_with_params(&mut expected, &salt, b"password", &params).unwrap();
+                    pool.derive(&mut output, &salt, b"password", &params).unwrap();
+                    assert_eq!(output, expected);
+                });
+            }
+        });
+    }
+}
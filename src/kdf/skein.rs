@@ -0,0 +1,134 @@
+/* *
+ * tsc - Implement Threefish, Skein, and CATENA cryptographic algorithms.
+ * Copyright (C) 2025 Stuart Calder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! HKDF-style key derivation from high-entropy secrets, built on Skein-512.
+//!
+//! extract() condenses input keying material into a pseudorandom key with Skein-MAC, and
+//! expand() stretches that key into output of any length with Skein's KDF mode, the output
+//! being independent for every distinct @info. These are for keys that are already
+//! high-entropy; derive keys from passwords with kdf::multi_threaded() instead.
+
+use crate::skein512::Skein512;
+use crate::tf512::NUM_KEY_BYTES;
+
+pub const NUM_PRK_BYTES: usize = NUM_KEY_BYTES;
+
+/// A pseudorandom key, zeroed when dropped.
+pub struct Prk {
+    bytes: [u8; NUM_PRK_BYTES],
+}
+
+impl Prk {
+    /// Use @bytes, which must already be uniformly random, as a pseudorandom key directly.
+    pub fn from_bytes(bytes: &[u8; NUM_PRK_BYTES]) -> Prk {
+        Prk { bytes: *bytes }
+    }
+    pub fn as_bytes(&self) -> &[u8; NUM_PRK_BYTES] {
+        &self.bytes
+    }
+}
+
+impl Drop for Prk {
+    fn drop(&mut self) {
+        rssc::op::secure_zero(&mut self.bytes);
+    }
+}
+
+/// Extract a pseudorandom key from the input keying material @ikm: the Skein-MAC of @ikm under
+/// @salt. An empty @salt is replaced by NUM_PRK_BYTES zero bytes, as in HKDF.
+pub fn extract(salt: &[u8], ikm: &[u8]) -> Prk {
+    const ZERO_SALT: [u8; NUM_PRK_BYTES] = [0u8; NUM_PRK_BYTES];
+    let salt = if salt.is_empty() { &ZERO_SALT[..] } else { salt };
+    let mut prk = Prk { bytes: [0u8; NUM_PRK_BYTES] };
+    Skein512::new().mac_u8(&mut prk.bytes, ikm, salt);
+    prk
+}
+
+/// Fill @output with key material derived from @prk for the context @info.
+/// The output length is bound into the derivation, so a shorter output is not a prefix
+/// of a longer one.
+pub fn expand(prk: &Prk, info: &[u8], output: &mut [u8]) {
+    Skein512::new().kdf(output, &prk.bytes, info);
+}
+
+/// Fill several named keys at once: each (label, output) entry of @outputs gets
+/// expand(@prk, label, output).
+pub fn derive_subkeys(prk: &Prk, outputs: &mut [(&[u8], &mut [u8])]) {
+    let mut skein512 = Skein512::new();
+    for (label, output) in outputs.iter_mut() {
+        skein512.kdf(output, &prk.bytes, label);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_depends_on_salt_and_ikm() {
+        let a = extract(b"salt", b"input keying material");
+        let b = extract(b"salt", b"input keying material");
+        let c = extract(b"other salt", b"input keying material");
+        let d = extract(b"salt", b"other keying material");
+        assert_eq!(a.as_bytes(), b.as_bytes());
+        assert_ne!(a.as_bytes(), c.as_bytes());
+        assert_ne!(a.as_bytes(), d.as_bytes());
+        assert_eq!(extract(b"", b"ikm").as_bytes(), extract(&[0u8; NUM_PRK_BYTES], b"ikm").as_bytes());
+    }
+
+    #[test]
+    fn expand_is_independent_per_info() {
+        let prk = extract(b"salt", b"input keying material");
+        let mut a = [0u8; 100];
+        let mut b = [0u8; 100];
+        let mut c = [0u8; 100];
+        expand(&prk, b"encryption", &mut a);
+        expand(&prk, b"authentication", &mut b);
+        expand(&prk, b"encryption", &mut c);
+        assert_eq!(a, c);
+        assert_ne!(a, b);
+        // No info is a prefix of another's output, nor a shorter output of a longer one.
+        let mut short = [0u8; 32];
+        expand(&prk, b"encryption", &mut short);
+        assert_ne!(&short[..], &a[..32]);
+        let mut other_prk = [0u8; 100];
+        expand(&extract(b"salt", b"other"), b"encryption", &mut other_prk);
+        assert_ne!(a, other_prk);
+    }
+
+    #[test]
+    fn derive_subkeys_matches_expand() {
+        let prk = extract(b"salt", b"input keying material");
+        let mut enc_key = [0u8; 32];
+        let mut mac_key = [0u8; 64];
+        let mut iv      = [0u8; 16];
+        derive_subkeys(&prk, &mut [
+            (b"encryption key", &mut enc_key),
+            (b"mac key",        &mut mac_key),
+            (b"iv",             &mut iv),
+        ]);
+        let mut expected = [0u8; 64];
+        expand(&prk, b"mac key", &mut expected);
+        assert_eq!(mac_key, expected);
+        let mut expected = [0u8; 32];
+        expand(&prk, b"encryption key", &mut expected);
+        assert_eq!(enc_key, expected);
+        let mut expected = [0u8; 16];
+        expand(&prk, b"iv", &mut expected);
+        assert_eq!(iv, expected);
+    }
+}
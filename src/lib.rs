@@ -20,6 +20,7 @@ pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
 
+pub mod error;
 pub mod tf512;
 pub mod ubi512;
 pub mod skein512;
@@ -28,9 +29,17 @@ pub mod rand;
 pub mod catena512;
 pub mod kdf;
 pub mod secure_buffer;
+mod base64;
+mod phc;
+#[cfg(feature = "async")]
+pub mod task;
+#[cfg(test)]
+mod catena512_vectors;
 //pub mod wots;
 extern crate rssc;
 
+pub use error::Error;
+
 #[cfg(test)]
 mod tests {
     use super::*;
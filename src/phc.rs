@@ -0,0 +1,54 @@
+/* *
+ * tsc - Implement Threefish, Skein, and CATENA cryptographic algorithms.
+ * Copyright (C) 2025 Stuart Calder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! The comma-separated key=value parameter field of PHC-style strings.
+
+/// Parse @field, a list like `g=8,gl=8,l=1`, into the values of @keys in order. Every key must
+/// appear exactly once and every value must be a decimal number without sign or leading zeros,
+/// so that each parameter set has exactly one encoding. Returns None otherwise.
+pub(crate) fn parse_params<const N: usize>(field: &str, keys: &[&str; N]) -> Option<[u64; N]> {
+    let mut values: [Option<u64>; N] = [None; N];
+    for pair in field.split(',') {
+        let (key, value) = pair.split_once('=')?;
+        let slot = keys.iter().position(|k| *k == key)?;
+        if values[slot].is_some() || value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) ||
+            (value.len() > 1 && value.starts_with('0')) {
+            return None;
+        }
+        values[slot] = Some(value.parse::<u64>().ok()?);
+    }
+    let mut parsed = [0u64; N];
+    for (p, v) in parsed.iter_mut().zip(values) {
+        *p = v?;
+    }
+    Some(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_canonical_fields_only() {
+        const KEYS: [&str; 2] = ["a", "bb"];
+        assert_eq!(parse_params("bb=10,a=0", &KEYS), Some([0, 10]));
+        for bad in ["a=1", "a=1,bb=2,a=1", "a=1,bb=02", "a=+1,bb=2", "a=1,bb=", "a=1,c=2", "a=1;bb=2",
+                    "a=1,bb=18446744073709551616"] {
+            assert_eq!(parse_params(bad, &KEYS), None, "{}", bad);
+        }
+    }
+}
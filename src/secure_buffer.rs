@@ -17,6 +17,7 @@
 
 #![allow(unused_imports)]
 pub use crate::tf512::NUM_BLOCK_BYTES;
+use crate::error::Error;
 pub use rssc::mmap;
 pub use rssc::c;
 pub use rssc::op;
@@ -52,8 +53,10 @@ impl Drop for SecureBufferAlternate {
             let layout = Layout::from_size_align(self.size, NUM_BLOCK_BYTES).unwrap();
             // Deallocate it.
             unsafe {alloc::dealloc(self.ptr, layout)};
-            // Reset to defaults.
-            *self = Self::default();
+            // Reset to defaults. Assigning a whole new value here would drop the old one,
+            // re-entering this function.
+            self.ptr  = std::ptr::null_mut();
+            self.size = 0usize;
         }
     }
 }
@@ -108,18 +111,31 @@ impl SecureBuffer {
             /* An uninitialized SecureBuffer does nothing during a nullify(). */
             _ => {}
         }
-        *self = Self::default();
+        // Only the tag needs resetting; assigning Self::default() would drop the old value and
+        // recurse back into nullify().
+        self.tag = 0u8;
     }
-    pub fn new_in_place(place: &mut SecureBuffer, requested_size: usize) -> Result<(),()>
+    pub fn new_in_place(place: &mut SecureBuffer, requested_size: usize) -> Result<(), Error>
+    {
+        Self::init_in_place(place, requested_size, mmap::HAS_INITSECRET)
+    }
+    /// Like new_in_place(), but skip the secret memory map and always use a plain aligned
+    /// allocation. The memory is still zeroed when dropped, but it isn't locked, so it isn't
+    /// subject to RLIMIT_MEMLOCK.
+    pub fn new_unlocked_in_place(place: &mut SecureBuffer, requested_size: usize) -> Result<(), Error>
+    {
+        Self::init_in_place(place, requested_size, false)
+    }
+    fn init_in_place(place: &mut SecureBuffer, requested_size: usize, try_secret_map: bool) -> Result<(), Error>
     {
         if requested_size == 0usize {
-            return Err(());
+            return Err(Error::BadParameter("requested_size must be nonzero"));
         }
         if place.is_initialized() {
             place.nullify();
         }
-        // Does our MemMap implementation support secret maps?
-        if mmap::HAS_INITSECRET {
+        // Does our MemMap implementation support secret maps, and do we want one?
+        if try_secret_map {
             // It does. Try to create a secret map.
             let res = Map::new_secret(requested_size);
             if res.is_ok() {
@@ -133,26 +149,33 @@ impl SecureBuffer {
             ptr: std::ptr::null_mut(),
             size: requested_size,
         };
-        let layout = Layout::from_size_align(requested_size, NUM_BLOCK_BYTES).unwrap();
+        let layout = Layout::from_size_align(requested_size, NUM_BLOCK_BYTES)
+            .map_err(|_| Error::BadParameter("requested_size is too large"))?;
         unsafe {sma.ptr = alloc::alloc(layout)};
         if sma.ptr.is_null() {
-            return Err(());
+            return Err(Error::AllocFail);
         }
         place.mem_union.mem_alt = ManuallyDrop::new(sma);
         place.tag = TAG_ALT;
         Ok(())
     }
-    pub fn new(requested_size: usize) -> Result<Self,()>
+    pub fn new(requested_size: usize) -> Result<Self, Error>
     {
         let mut sm = Self::default();
         Self::new_in_place(&mut sm, requested_size)?;
         Ok(sm)
     }
+    pub fn new_unlocked(requested_size: usize) -> Result<Self, Error>
+    {
+        let mut sm = Self::default();
+        Self::new_unlocked_in_place(&mut sm, requested_size)?;
+        Ok(sm)
+    }
     pub fn is_initialized(&self) -> bool
     {
         self.tag == TAG_MAP || self.tag == TAG_ALT
     }
-    pub fn get_raw_ptr(&mut self) -> Result<*mut u8, ()>
+    pub fn get_raw_ptr(&mut self) -> Result<*mut u8, Error>
     {
         match self.tag {
             TAG_MAP => {
@@ -167,10 +190,10 @@ impl SecureBuffer {
                 };
                 Ok(alt.ptr)
             },
-            _ => Err(())
+            _ => Err(Error::NotInit)
         }
     }
-    pub fn get_slice(&mut self) -> Result<&mut [u8], ()>
+    pub fn get_slice(&mut self) -> Result<&mut [u8], Error>
     {
         match self.tag {
             TAG_MAP => {
@@ -197,10 +220,10 @@ impl SecureBuffer {
                 };
                 Ok(m)
             },
-            _ => Err(())
+            _ => Err(Error::NotInit)
         }
     }
-    pub fn get_size(&self) -> Result<usize, ()>
+    pub fn get_size(&self) -> Result<usize, Error>
     {
         match self.tag {
             TAG_MAP => {
@@ -215,10 +238,10 @@ impl SecureBuffer {
                 };
                 Ok(alt.size)
             },
-            _ => Err(())
+            _ => Err(Error::NotInit)
         }
     }
-    pub fn resize(&mut self, new_size: usize) -> Result<(), ()>
+    pub fn resize(&mut self, new_size: usize) -> Result<(), Error>
     {
         if new_size == self.get_size()? {
             return Ok(());
@@ -228,7 +251,7 @@ impl SecureBuffer {
                 let map = unsafe {
                     &mut *self.mem_union.mem_map
                 };
-                map.resize(new_size)?;
+                map.resize(new_size).map_err(|_| Error::AllocFail)?;
             },
             TAG_ALT => {
                 // Get a mutable reference to the SecureBufferAlternate.
@@ -239,12 +262,12 @@ impl SecureBuffer {
                 // Get the layout and try to allocate new memory.
                 let layout_res = Layout::from_size_align(new_size, NUM_BLOCK_BYTES);
                 if layout_res.is_err() {
-                    return Err(());
+                    return Err(Error::BadParameter("new_size is too large"));
                 }
                 let layout = layout_res.unwrap();
                 let p: *mut u8 = unsafe {alloc::alloc(layout)};
                 if p.is_null() {
-                    return Err(());
+                    return Err(Error::AllocFail);
                 }
                 // Form a slice that references the newly allocated memory.
                 let p_slice = unsafe {std::slice::from_raw_parts_mut(p, new_size)};
@@ -252,7 +275,7 @@ impl SecureBuffer {
                 // resizing.
                 let alt_slice_res = self.get_slice();
                 if alt_slice_res.is_err() {
-                    return Err(());
+                    return Err(Error::NotInit);
                 }
                 let alt_slice = alt_slice_res.unwrap();
                 let copy_size = if new_size > alt_size {
@@ -278,7 +301,7 @@ impl SecureBuffer {
                     );
                 }
             },
-            _ => return Err(())
+            _ => return Err(Error::NotInit)
         }
         Ok(())
     }
@@ -287,3 +310,43 @@ impl SecureBuffer {
         self.tag
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locked_allocation_path() {
+        let mut buffer = SecureBuffer::new(4096).unwrap();
+        // Without secret map support, or when the map can't be locked, the buffer falls back.
+        match buffer.get_tag() {
+            TAG_MAP if mmap::HAS_INITSECRET => {},
+            TAG_ALT => {},
+            tag => panic!("unexpected tag {}", tag)
+        }
+        assert_eq!(buffer.get_size().unwrap(), 4096);
+        assert_eq!(buffer.get_raw_ptr().unwrap() as usize % NUM_BLOCK_BYTES, 0);
+        let slice = buffer.get_slice().unwrap();
+        slice.fill(0xa5);
+        assert!(slice.iter().all(|&b| b == 0xa5));
+        buffer.nullify();
+        assert!(!buffer.is_initialized());
+        assert_eq!(buffer.get_size(), Err(Error::NotInit));
+        // Re-initializing in place releases the old allocation first.
+        SecureBuffer::new_in_place(&mut buffer, 128).unwrap();
+        assert_eq!(buffer.get_size().unwrap(), 128);
+    }
+
+    #[test]
+    fn locked_allocation_errors() {
+        assert!(matches!(SecureBuffer::new(0), Err(Error::BadParameter(_))));
+        // Too large for any secret map, and too large for a Layout once the fallback is taken.
+        assert!(matches!(
+            SecureBuffer::new(usize::MAX),
+            Err(Error::BadParameter("requested_size is too large"))
+        ));
+        let mut buffer = SecureBuffer::new_unlocked(64).unwrap();
+        assert!(matches!(SecureBuffer::new_in_place(&mut buffer, 0), Err(Error::BadParameter(_))));
+        assert_eq!(buffer.get_tag(), TAG_ALT);
+    }
+}
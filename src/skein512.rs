@@ -93,4 +93,32 @@ impl Skein512 {
         self.ubi512.chain_message(input);
         self.ubi512.chain_output(output);
     }
+    /// Skein-MAC under @key, which may be any nonzero length, with an output of any length.
+    pub fn mac_u8(
+        &mut self,
+        output: &mut [u8],
+        input:  &[u8],
+        key:    &[u8])
+    {
+        self.ubi512.threefish512.key.fill(0u64);
+        self.ubi512.chain_key(key);
+        self.ubi512.chain_config({output.len() as u64} * 8u64);
+        self.ubi512.chain_message(input);
+        self.ubi512.chain_output(output);
+    }
+    /// Skein-512 in KDF mode: derive @output, of any length, from the master key @key, which
+    /// may be any nonzero length, and the key identifier @identifier. Different identifiers
+    /// give independent outputs.
+    pub fn kdf(
+        &mut self,
+        output:     &mut [u8],
+        key:        &[u8],
+        identifier: &[u8])
+    {
+        self.ubi512.threefish512.key.fill(0u64);
+        self.ubi512.chain_key(key);
+        self.ubi512.chain_config({output.len() as u64} * 8u64);
+        self.ubi512.chain_kdf(identifier);
+        self.ubi512.chain_output(output);
+    }
 }
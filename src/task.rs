@@ -0,0 +1,150 @@
+/* *
+ * tsc - Implement Threefish, Skein, and CATENA cryptographic algorithms.
+ * Copyright (C) 2025 Stuart Calder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! A minimal, runtime-independent way to await blocking work: spawn() runs a closure on a
+//! dedicated OS thread and returns a TaskFuture that completes with its result through a
+//! oneshot channel built from std primitives.
+
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use crate::error::Error;
+
+struct State<T> {
+    result: Option<Result<T, Error>>,
+    waker:  Option<Waker>,
+}
+
+struct Shared<T> {
+    state:  Mutex<State<T>>,
+    cancel: AtomicBool,
+}
+
+/// The result of work running on its own thread. Dropping it before completion sets the
+/// work's cancel flag, and any result that arrives afterwards is dropped unread, so results
+/// should zero themselves on drop.
+pub struct TaskFuture<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Run @work on a new thread, passing it a cancel flag that is set when the returned future
+/// is dropped.
+pub(crate) fn spawn<T, F>(work: F) -> TaskFuture<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&AtomicBool) -> Result<T, Error> + Send + 'static,
+{
+    let shared = Arc::new(Shared {
+        state:  Mutex::new(State { result: None, waker: None }),
+        cancel: AtomicBool::new(false),
+    });
+    let worker_shared = Arc::clone(&shared);
+    let spawned = thread::Builder::new()
+        .name("tsc-task".to_string())
+        .spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| work(&worker_shared.cancel)))
+                .unwrap_or(Err(Error::ThreadPanic));
+            let mut state = worker_shared.state.lock().unwrap_or_else(|e| e.into_inner());
+            if worker_shared.cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+    if spawned.is_err() {
+        shared.state.lock().unwrap_or_else(|e| e.into_inner()).result = Some(Err(Error::AllocFail));
+    }
+    TaskFuture { shared }
+}
+
+impl<T> Future for TaskFuture<T> {
+    type Output = Result<T, Error>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Drop for TaskFuture<T> {
+    fn drop(&mut self) {
+        self.shared.cancel.store(true, Ordering::Relaxed);
+        // Drop a result that completed but was never polled.
+        let result = self.shared.state.lock().unwrap_or_else(|e| e.into_inner()).result.take();
+        drop(result);
+    }
+}
+
+/// Drive @future to completion on the current thread.
+#[cfg(test)]
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    use std::task::Wake;
+
+    struct ThreadWaker(thread::Thread);
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending       => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn completes_with_result() {
+        assert_eq!(block_on(spawn(|_| Ok(42u32))), Ok(42));
+        assert_eq!(block_on(spawn(|_| Err::<u32, _>(Error::AuthFail))), Err(Error::AuthFail));
+        assert_eq!(block_on(spawn(|_| -> Result<u32, Error> { panic!("worker") })), Err(Error::ThreadPanic));
+    }
+
+    #[test]
+    fn drop_sets_cancel_flag() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let future = spawn(move |cancel| {
+            while !cancel.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            sender.send(()).unwrap();
+            Err::<(), _>(Error::Cancelled)
+        });
+        drop(future);
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    }
+}
@@ -55,11 +55,23 @@ pub const OCB_TOP_4_BITS: u64 = 0b1111u64 << 60;
 pub const OCB_TAG_WORDS: usize = 8;
 pub const OCB_TAG_BYTES: usize = 64;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum OcbError {
     TagMismatch,
     InvalidLength
 }
 
+impl std::fmt::Display for OcbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OcbError::TagMismatch   => f.write_str("OCB authentication tag mismatch"),
+            OcbError::InvalidLength => f.write_str("OCB input and output lengths differ"),
+        }
+    }
+}
+
+impl std::error::Error for OcbError {}
+
 macro_rules! store_word {
     ($key_schedule:expr,
      $key_words:expr,
@@ -17,6 +17,7 @@
 
 use crate::tf512;
 use tf512::Threefish512Dynamic;
+use std::cmp::min;
 
 // The first block is being processed.
 pub const TWEAK_FIRST_BIT:  u8 = 0x40u8;
@@ -213,6 +214,58 @@ impl Ubi512
         }
         parity_encipher_xor!(self);
     }// ~ chain_message()
+    /// Chain the concatenation of @parts as a message without first copying them into one
+    /// buffer. The result is identical to chain_message() on the concatenated input.
+    pub fn chain_message_parts(
+        &mut self,
+        parts: &[&[u8]])
+    {
+        self.chain_parts(TYPEMASK_MSG, parts);
+    }
+    /// Chain @identifier as the key identifier block of Skein's KDF mode.
+    pub fn chain_kdf(
+        &mut self,
+        identifier: &[u8])
+    {
+        self.chain_parts(TYPEMASK_KDF, &[identifier]);
+    }
+    fn chain_parts(
+        &mut self,
+        type_mask: u8,
+        parts: &[&[u8]])
+    {
+        initialize_tweak!(self, type_mask);
+        let mut position = 0u64;
+        let mut filled   = 0usize;
+        for part in parts {
+            let mut part: &[u8] = part;
+            while !part.is_empty() {
+                if filled == NUM_HASH_BYTES {
+                    // The buffered block is full and more input follows, so it isn't the last.
+                    position += NUM_HASH_BYTES as u64;
+                    *get_tweak_position_mut!(self) = position.to_le();
+                    parity_encipher_xor!(self);
+                    *get_tweak_flags_mut!(self) &= TWEAK_FIRST_MASK;
+                    filled = 0;
+                }
+                let n = min(NUM_HASH_BYTES - filled, part.len());
+                {
+                    let msg_bytes: &mut [u8] = as_bytes_mut!(&mut self.msg, NUM_HASH_WORDS);
+                    msg_bytes[filled..filled + n].copy_from_slice(&part[..n]);
+                }
+                filled += n;
+                part = &part[n..];
+            }
+        }
+        {
+            let msg_bytes: &mut [u8] = as_bytes_mut!(&mut self.msg, NUM_HASH_WORDS);
+            msg_bytes[filled..].fill(0u8);
+        }
+        position += filled as u64;
+        *get_tweak_flags_mut!(self)    |= TWEAK_LAST_BIT;
+        *get_tweak_position_mut!(self) = position.to_le();
+        parity_encipher_xor!(self);
+    }// ~ chain_parts()
     pub fn chain_output(
         &mut self,
         output: &mut [u8])
@@ -272,6 +325,14 @@ impl Ubi512
         }.copy_from_slice(key);
         parity_encipher_xor!(self);
     }// ~ chain_key()
+    /// Chain a key of any nonzero length. Identical to chain_key_u8() for NUM_HASH_BYTES keys.
+    pub fn chain_key(
+        &mut self,
+        key: &[u8])
+    {
+        debug_assert!(!key.is_empty());
+        self.chain_parts(TYPEMASK_KEY, &[key]);
+    }
     pub fn chain_key_u64(
         &mut self,
         key: &[u64])
@@ -282,3 +343,53 @@ impl Ubi512
         parity_encipher_xor!(self);
     }// ~ chain_key()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skein512::NATIVE_INIT;
+
+    fn hash_whole(input: &[u8]) -> [u8; NUM_HASH_BYTES] {
+        let mut ubi = Ubi512::new();
+        let mut out = [0u8; NUM_HASH_BYTES];
+        ubi.threefish512.key[..NUM_HASH_WORDS].copy_from_slice(&NATIVE_INIT);
+        ubi.chain_message(input);
+        ubi.chain_output_native(&mut out);
+        out
+    }
+
+    fn hash_parts(parts: &[&[u8]]) -> [u8; NUM_HASH_BYTES] {
+        let mut ubi = Ubi512::new();
+        let mut out = [0u8; NUM_HASH_BYTES];
+        ubi.threefish512.key[..NUM_HASH_WORDS].copy_from_slice(&NATIVE_INIT);
+        ubi.chain_message_parts(parts);
+        ubi.chain_output_native(&mut out);
+        out
+    }
+
+    #[test]
+    fn message_parts_match_whole_message() {
+        let data: Vec<u8> = (0..300u32).map(|i| (i * 31 + 7) as u8).collect();
+        for len in [0usize, 1, 63, 64, 65, 127, 128, 129, 227, 300] {
+            let input = &data[..len];
+            let expected = hash_whole(input);
+            assert_eq!(hash_parts(&[input]), expected);
+            for split in [0usize, 1, 64, 70, len / 2, len] {
+                let split = split.min(len);
+                let (a, b) = input.split_at(split);
+                assert_eq!(hash_parts(&[a, b]), expected);
+                assert_eq!(hash_parts(&[&[], a, &[], b, &[]]), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn chain_key_matches_chain_key_u8() {
+        let key: Vec<u8> = (0..NUM_HASH_BYTES as u8).collect();
+        let mut a = Ubi512::new();
+        let mut b = Ubi512::new();
+        a.chain_key_u8(&key);
+        b.chain_key(&key);
+        assert_eq!(a.threefish512.key, b.threefish512.key);
+    }
+}